@@ -0,0 +1,289 @@
+use crate::constants;
+use crate::environment;
+use crate::interaction;
+use crate::pod_handler;
+use crate::tools;
+use crate::utils;
+use clap::ValueEnum;
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// Where a workspace backup is written
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum BackupTarget {
+    /// Tarball in the local backups/ directory
+    Local,
+    /// Tarball in your personal NFS data directory
+    Nfs,
+}
+
+// Whether to back up a pod's workspace before uninstalling it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BackupPolicy {
+    Ask,
+    Skip,
+    Backup(BackupTarget, Vec<String>),
+}
+
+// Look up the pod paths of the named backup sets, all of them when empty
+fn resolve_paths(names: &[String]) -> Result<Vec<&'static str>, Box<dyn Error>> {
+    if names.is_empty() {
+        return Ok(constants::BACKUP_PATHS
+            .iter()
+            .map(|(_, path)| *path)
+            .collect());
+    }
+    names
+        .iter()
+        .map(|name| {
+            constants::BACKUP_PATHS
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, path)| *path)
+                .ok_or_else(|| {
+                    let known: Vec<&str> =
+                        constants::BACKUP_PATHS.iter().map(|(n, _)| *n).collect();
+                    format!(
+                        "Unknown backup path {} (available: {})",
+                        name,
+                        known.join(", ")
+                    )
+                    .into()
+                })
+        })
+        .collect()
+}
+
+// Shell script that writes a gzipped tar of the given paths to its first
+// argument ("-" for stdout), creating the directory given as second argument
+// first unless it is empty. The paths follow as further arguments so they are
+// never interpreted by the shell; only the literal `$HOME` is expanded, to the
+// pod user's home. Missing paths are an error when the third argument is
+// non-empty (paths picked by name) and skipped with a notice otherwise. They
+// are archived relative to / so they can be unpacked into a fresh pod with
+// `tar xzf - -C /`.
+const TAR_SCRIPT: &str = r#"out="$1"; dir="$2"; strict="$3"; shift 3; if [ -n "$dir" ]; then mkdir -p "$dir" || exit 1; fi; n=$#; while [ "$n" -gt 0 ]; do case "$1" in '$HOME') p="$HOME" ;; *) p="$1" ;; esac; shift; n=$((n - 1)); p="${p#/}"; if [ -n "$p" ] && [ -e "/$p" ]; then set -- "$@" "$p"; elif [ -n "$strict" ]; then echo "/$p does not exist in the pod" >&2; exit 1; else echo "Skipping /$p, which does not exist in the pod" >&2; fi; done; [ $# -gt 0 ] || { echo "Nothing to back up" >&2; exit 1; }; tar czf "$out" -C / "$@""#;
+
+// Arguments of `kubectl exec` running the tar script in a pod; `strict`
+// makes missing paths an error instead of skipping them
+fn tar_command<'a>(
+    pod_name: &'a str,
+    paths: &[&'a str],
+    output: &'a str,
+    dir: &'a str,
+    strict: bool,
+) -> Vec<&'a str> {
+    let strict = if strict { "strict" } else { "" };
+    let mut args = vec![
+        "exec", pod_name, "--", "sh", "-c", TAR_SCRIPT, "sh", output, dir, strict,
+    ];
+    args.extend_from_slice(paths);
+    args
+}
+
+fn local_backup_dir() -> io::Result<PathBuf> {
+    Ok(env::current_dir()?.join("backups"))
+}
+
+fn nfs_backup_dir(pod_name: &str) -> Result<String, Box<dyn Error>> {
+    let user_info = environment::UserInfo::load()?;
+    Ok(format!(
+        "{}/backups",
+        pod_handler::personal_data_dir(pod_name, &user_info.user)?
+    ))
+}
+
+// Archive the selected paths of a pod and return where the archive was written
+pub fn backup_pod(
+    pod_name: &str,
+    target: BackupTarget,
+    names: &[String],
+) -> Result<String, Box<dyn Error>> {
+    let paths = resolve_paths(names)?;
+    // Paths picked by name must exist; the full set skips what a pod lacks
+    let strict = !names.is_empty();
+    let archive_name = format!(
+        "{}-{}.tar.gz",
+        pod_handler::release_name(pod_name),
+        utils::unix_timestamp()
+    );
+    println!("Backing up {} from {}...", paths.join(", "), pod_name);
+
+    match target {
+        BackupTarget::Local => {
+            let backup_dir = local_backup_dir()?;
+            fs::create_dir_all(&backup_dir)?;
+            let archive_path = backup_dir.join(&archive_name);
+            let file = File::create(&archive_path)?;
+            let status = Command::new(tools::kubectl())
+                .args(tar_command(pod_name, &paths, "-", "", strict))
+                .stdout(Stdio::from(file))
+                .status()?;
+            if !status.success() {
+                fs::remove_file(&archive_path)?;
+                return Err(
+                    format!("Backup of {} failed with status: {}", pod_name, status).into(),
+                );
+            }
+            Ok(archive_path.display().to_string())
+        }
+        BackupTarget::Nfs => {
+            let backup_dir = nfs_backup_dir(pod_name)?;
+            let archive_path = format!("{}/{}", backup_dir, archive_name);
+            utils::run_cmd(
+                &tools::kubectl(),
+                &tar_command(pod_name, &paths, &archive_path, &backup_dir, strict),
+            )?;
+            Ok(archive_path)
+        }
+    }
+}
+
+// Ask whether and where to back up a pod before uninstalling it
+fn ask_backup() -> Result<BackupPolicy, Box<dyn Error>> {
+    if !interaction::confirm("Back up the pod's workspace before uninstalling?")? {
+        return Ok(BackupPolicy::Skip);
+    }
+    print!("Save to 1. local backups/ directory 2. personal NFS directory [1]: ");
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let target = match input.trim() {
+        "" | "1" => BackupTarget::Local,
+        "2" => BackupTarget::Nfs,
+        _ => return Err("Invalid input, please enter 1 or 2".into()),
+    };
+
+    let known: Vec<&str> = constants::BACKUP_PATHS.iter().map(|(n, _)| *n).collect();
+    print!("Paths to back up ({}) [all]: ", known.join(", "));
+    io::stdout().flush()?;
+    input.clear();
+    io::stdin().read_line(&mut input)?;
+    let names = input
+        .split([',', ' '])
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .collect();
+    Ok(BackupPolicy::Backup(target, names))
+}
+
+// Back up a pod according to the policy; an error means the uninstall
+// should not go ahead
+pub fn backup_before_uninstall(
+    pod_name: &str,
+    policy: &BackupPolicy,
+) -> Result<(), Box<dyn Error>> {
+    let policy = match policy {
+        BackupPolicy::Ask => ask_backup()?,
+        policy => policy.clone(),
+    };
+    let BackupPolicy::Backup(target, names) = policy else {
+        return Ok(());
+    };
+    let archive = backup_pod(pod_name, target, &names)?;
+    println!("Workspace backed up to {}", archive);
+    Ok(())
+}
+
+// Unpack a backup archive into a pod. Local archives are streamed from this
+// machine, remote ones are read from the pod's filesystem (relative paths are
+// looked up in the personal NFS backups directory).
+pub fn restore_pod(pod_name: &str, archive: &str, remote: bool) -> Result<(), Box<dyn Error>> {
+    println!("Restoring {} into {}...", archive, pod_name);
+    if remote {
+        let archive_path = if archive.starts_with('/') {
+            archive.to_string()
+        } else {
+            format!("{}/{}", nfs_backup_dir(pod_name)?, archive)
+        };
+        utils::run_cmd(
+            &tools::kubectl(),
+            &[
+                "exec",
+                pod_name,
+                "--",
+                "tar",
+                "xzf",
+                &archive_path,
+                "-C",
+                "/",
+            ],
+        )?;
+    } else {
+        let archive_path = Path::new(archive);
+        let archive_path = if archive_path.exists() {
+            archive_path.to_path_buf()
+        } else {
+            local_backup_dir()?.join(archive)
+        };
+        let file = File::open(&archive_path)
+            .map_err(|e| format!("Cannot open {}: {}", archive_path.display(), e))?;
+        let status = Command::new(tools::kubectl())
+            .args(["exec", "-i", pod_name, "--", "tar", "xzf", "-", "-C", "/"])
+            .stdin(Stdio::from(file))
+            .status()?;
+        if !status.success() {
+            return Err(format!("Restore into {} failed with status: {}", pod_name, status).into());
+        }
+    }
+    println!("Restore complete.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tar_command() {
+        let dir = env::temp_dir().join(format!("thumed_backup_{}", std::process::id()));
+        let home = dir.join("home");
+        fs::create_dir_all(&home).unwrap();
+        fs::write(home.join("notes.txt"), "notes").unwrap();
+        let missing = dir.join("missing");
+        let missing = missing.to_str().unwrap();
+        let output = dir.join("backup.tar.gz");
+        let output = output.to_str().unwrap();
+
+        // Run the arguments kubectl would pass after `--` through sh
+        let run = |paths: &[&str], strict: bool| {
+            let args = tar_command("pod", paths, output, "", strict);
+            let _ = fs::remove_file(output);
+            Command::new(args[3])
+                .args(&args[4..])
+                .env("HOME", &home)
+                .stderr(Stdio::null())
+                .status()
+                .unwrap()
+                .success()
+        };
+        let archived = || -> Vec<String> {
+            let file = File::open(output).unwrap();
+            let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+            archive
+                .entries()
+                .unwrap()
+                .map(|e| e.unwrap().path().unwrap().display().to_string())
+                .collect()
+        };
+        let notes = home.join("notes.txt").display().to_string();
+        let notes = notes.trim_start_matches('/');
+
+        // $HOME is expanded in the pod
+        assert!(run(&["$HOME"], true));
+        assert!(archived().iter().any(|p| p == notes));
+
+        // Missing paths are skipped for the full set but fail when requested
+        assert!(run(&["$HOME", missing], false));
+        assert!(archived().iter().any(|p| p == notes));
+        assert!(!run(&["$HOME", missing], true));
+        assert!(!run(&[missing], false));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use crate::tools::{self, Tool};
+use crate::{checksum, utils};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use tar::Archive;
+
+// Name of the manifest at the root of a bundle
+const MANIFEST_FILE: &str = "manifest.json";
+// Targets a bundle can be built for
+const SUPPORTED_OS: &[&str] = &["linux", "darwin", "windows"];
+const SUPPORTED_ARCH: &[&str] = &["amd64", "arm64"];
+
+// Whether a bundle path is a .tar.gz archive rather than a directory
+fn is_archive(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+// Parse a target such as "linux/amd64"
+fn parse_target(target: &str) -> Result<(String, String), Box<dyn Error>> {
+    let (os, arch) = target
+        .split_once('/')
+        .ok_or_else(|| format!("Invalid target {}, expected <os>/<arch>", target))?;
+    if !SUPPORTED_OS.contains(&os) || !SUPPORTED_ARCH.contains(&arch) {
+        return Err(format!(
+            "Unsupported target {} (OS: {}, architecture: {})",
+            target,
+            SUPPORTED_OS.join(", "),
+            SUPPORTED_ARCH.join(", ")
+        )
+        .into());
+    }
+    Ok((os.to_string(), arch.to_string()))
+}
+
+// Pack the content of `dir` into a .tar.gz archive
+fn pack(dir: &Path, output: &Path) -> Result<(), Box<dyn Error>> {
+    let mut builder = tar::Builder::new(GzEncoder::new(
+        File::create(output)?,
+        flate2::Compression::default(),
+    ));
+    builder.append_dir_all(".", dir)?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+// Unpack a .tar.gz bundle into `dir`, refusing entries that point outside it
+fn unpack(archive_path: &Path, dir: &Path) -> Result<(), Box<dyn Error>> {
+    let mut archive = Archive::new(GzDecoder::new(File::open(archive_path)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if !utils::is_safe_entry_path(&path) {
+            return Err(format!(
+                "Refusing to unpack {}: entry {} points outside the bundle",
+                archive_path.display(),
+                path.display()
+            )
+            .into());
+        }
+        let target = dir.join(&path);
+        if entry.header().entry_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if entry.header().entry_type().is_file() {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            entry.unpack(&target)?;
+        }
+    }
+    Ok(())
+}
+
+// Download kubectl and helm for each "<os>/<arch>" target into a bundle, a
+// directory or a .tar.gz archive depending on `output`, for installing them
+// later without internet access with `check-env --offline-bundle`
+pub fn create(output: &Path, targets: &[String]) -> Result<(), Box<dyn Error>> {
+    let targets = if targets.is_empty() {
+        let (os, arch) = utils::get_os_and_arch()?;
+        vec![(os, arch)]
+    } else {
+        targets
+            .iter()
+            .map(|t| parse_target(t))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    let archive = is_archive(output);
+    let dir = if archive {
+        let mut staging = output.as_os_str().to_owned();
+        staging.push(".staging");
+        PathBuf::from(staging)
+    } else {
+        output.to_path_buf()
+    };
+    let result = fill_bundle(&dir, &targets).and_then(|_| {
+        if archive {
+            pack(&dir, output)?;
+        }
+        Ok(())
+    });
+    if archive {
+        let _ = fs::remove_dir_all(&dir);
+        if result.is_err() {
+            let _ = fs::remove_file(output);
+        }
+    }
+    result?;
+    println!("Bundle written to {}", output.display());
+    Ok(())
+}
+
+fn fill_bundle(dir: &Path, targets: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+    let bin_dir = tools::bin_dir()?;
+    let mut files = Vec::new();
+    for (os, arch) in targets {
+        let target_dir = dir.join(format!("{}-{}", os, arch));
+        fs::create_dir_all(&target_dir)?;
+        for tool in tools::ALL_TOOLS {
+            let version = tools::desired_version(&bin_dir, tool);
+            println!("Adding {} {} for {}/{}", tool.name(), version, os, arch);
+            match tool {
+                Tool::Kubectl => utils::download_kubectl_for(&target_dir, &version, os, arch)?,
+                Tool::Helm => utils::download_helm_for(&target_dir, &version, os, arch)?,
+            }
+            let file = format!("{}-{}/{}", os, arch, utils::exe_name_for(os, tool.name()));
+            let mut entry = json!({
+                "tool": tool.name(),
+                "version": version,
+                "os": os,
+                "arch": arch,
+                "file": file,
+                "sha256": checksum::sha256_file(&dir.join(&file))?,
+            });
+            // helm is verified again later through its release archive
+            if tool == Tool::Helm {
+                entry["archive"] = json!(format!(
+                    "{}-{}/{}",
+                    os,
+                    arch,
+                    utils::helm_artifact(&version, os, arch)
+                ));
+            }
+            files.push(entry);
+        }
+    }
+    let manifest = json!({ "created": utils::unix_timestamp(), "files": files });
+    fs::write(
+        dir.join(MANIFEST_FILE),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    Ok(())
+}
+
+// Install kubectl and helm for this machine from a bundle directory or
+// archive made by `create`, checking every file against the manifest
+pub fn install(bundle: &Path, bin_dir: &Path) -> Result<(), Box<dyn Error>> {
+    if bundle.is_dir() {
+        return install_from_dir(bundle, bin_dir);
+    }
+    if !bundle.is_file() {
+        return Err(format!("Bundle {} not found", bundle.display()).into());
+    }
+    let staging = bin_dir.join(".bundle");
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging)?;
+    let result = unpack(bundle, &staging).and_then(|_| install_from_dir(&staging, bin_dir));
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+fn install_from_dir(dir: &Path, bin_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let manifest: Value = serde_json::from_str(
+        &fs::read_to_string(dir.join(MANIFEST_FILE))
+            .map_err(|e| format!("Cannot read the bundle manifest: {}", e))?,
+    )?;
+    let (os, arch) = utils::get_os_and_arch()?;
+    let files = manifest["files"].as_array().cloned().unwrap_or_default();
+
+    for tool in tools::ALL_TOOLS {
+        let entry = files
+            .iter()
+            .find(|f| {
+                f["tool"] == tool.name() && f["os"] == os.as_str() && f["arch"] == arch.as_str()
+            })
+            .ok_or_else(|| format!("The bundle has no {} for {}/{}", tool.name(), os, arch))?;
+        let (Some(version), Some(file), Some(sha256)) = (
+            entry["version"].as_str(),
+            entry["file"].as_str(),
+            entry["sha256"].as_str(),
+        ) else {
+            return Err(format!("Invalid manifest entry for {}", tool.name()).into());
+        };
+        let archive = entry["archive"].as_str();
+        for file in std::iter::once(file).chain(archive) {
+            if !utils::is_safe_entry_path(Path::new(file)) {
+                return Err(format!("Invalid file {} in the bundle manifest", file).into());
+            }
+        }
+        tools::install_from_file(
+            bin_dir,
+            tool,
+            version,
+            &dir.join(file),
+            sha256,
+            archive.map(|archive| dir.join(archive)).as_deref(),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_install_from_bundle() {
+        let root = std::env::temp_dir().join(format!("thumed_bundle_{}", std::process::id()));
+        let (bundle, bin_dir) = (root.join("bundle"), root.join("bin"));
+        let (os, arch) = utils::get_os_and_arch().unwrap();
+        let target_dir = bundle.join(format!("{}-{}", os, arch));
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::create_dir_all(&bin_dir).unwrap();
+
+        let mut files = Vec::new();
+        for tool in tools::ALL_TOOLS {
+            let file = format!("{}-{}/{}", os, arch, utils::exe_name_for(&os, tool.name()));
+            fs::write(bundle.join(&file), tool.name()).unwrap();
+            files.push(json!({
+                "tool": tool.name(), "version": "v1.0.0", "os": os, "arch": arch, "file": file,
+                "sha256": checksum::sha256_file(&bundle.join(&file)).unwrap(),
+            }));
+        }
+        fs::write(
+            bundle.join(MANIFEST_FILE),
+            json!({ "files": files }).to_string(),
+        )
+        .unwrap();
+
+        // From an archive, leaving no staging directory behind
+        let archive = root.join("bundle.tar.gz");
+        pack(&bundle, &archive).unwrap();
+        install(&archive, &bin_dir).unwrap();
+        let kubectl = bin_dir.join(utils::exe_name_for(&os, "kubectl"));
+        assert_eq!(fs::read_to_string(&kubectl).unwrap(), "kubectl");
+        assert!(!bin_dir.join(".bundle").exists());
+
+        // A file that does not match the manifest is rejected
+        fs::write(bundle.join(files[1]["file"].as_str().unwrap()), "changed").unwrap();
+        fs::remove_dir_all(bin_dir.join("versions")).unwrap();
+        assert!(install(&bundle, &bin_dir).is_err());
+
+        assert!(parse_target("linux/amd64").is_ok());
+        assert!(parse_target("plan9/mips").is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{constants, utils};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Result of checking an installed binary against the trusted checksums
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verification {
+    /// Matches the trusted checksum of its release
+    Verified,
+    /// Not a copy of any version installed by this tool, e.g. installed by
+    /// hand, by an older version or replaced since
+    Unknown,
+    /// Differs from the trusted checksum: tampered with or corrupted
+    Mismatch { expected: String, actual: String },
+}
+
+// SHA-256 of a file as lowercase hex
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// Checksum from the content of a .sha256 or sha256sum file: the first word,
+// which must be 64 hex digits
+pub fn parse_checksum(content: &str) -> Option<String> {
+    let checksum = content.split_whitespace().next()?.to_ascii_lowercase();
+    if checksum.len() == 64 && checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(checksum)
+    } else {
+        None
+    }
+}
+
+// Trusted checksums file: the one in TRUSTED_CHECKSUMS_ENV when set,
+// otherwise config/trusted_checksums.sha256
+fn trusted_checksums_path() -> io::Result<PathBuf> {
+    match env::var(constants::TRUSTED_CHECKSUMS_ENV) {
+        Ok(path) if !path.is_empty() => Ok(PathBuf::from(path)),
+        _ => Ok(env::current_dir()?
+            .join("config")
+            .join("trusted_checksums.sha256")),
+    }
+}
+
+// Checksum listed for `name` in sha256sum formatted content
+fn find_checksum(content: &str, name: &str) -> Option<String> {
+    parse_checksum_lines(content)
+        .into_iter()
+        .find(|(n, _)| n == name)
+        .and_then(|(_, checksum)| parse_checksum(&checksum))
+}
+
+// Expected checksum of a release artifact: the pinned one shipped with the
+// tool when there is one, then the one in the trusted checksums file,
+// otherwise the first checksum file found at `checksum_urls`, tried in order
+pub fn expected_checksum(
+    artifact: &str,
+    checksum_urls: &[String],
+) -> Result<String, Box<dyn Error>> {
+    if let Some((_, checksum)) = constants::PINNED_CHECKSUMS
+        .iter()
+        .find(|(name, _)| *name == artifact)
+    {
+        return Ok(checksum.to_string());
+    }
+    let trusted_path = trusted_checksums_path()?;
+    if let Ok(content) = fs::read_to_string(&trusted_path) {
+        if let Some(checksum) = find_checksum(&content, artifact) {
+            return Ok(checksum);
+        }
+    }
+    for url in checksum_urls {
+        match utils::fetch_text(url) {
+            Ok(content) => match parse_checksum(&content) {
+                Some(checksum) => return Ok(checksum),
+                None => eprintln!("No checksum found at {}", url),
+            },
+            Err(e) => eprintln!("Could not fetch checksum from {}: {}", url, e),
+        }
+    }
+    Err(format!(
+        "No trusted checksum for {}: it is not pinned, not listed in {} and the official sources could not be reached",
+        artifact,
+        trusted_path.display()
+    )
+    .into())
+}
+
+// Check a downloaded file against its expected checksum, deleting it when it
+// does not match
+pub fn verify_download(path: &Path, expected: &str) -> Result<(), Box<dyn Error>> {
+    let actual = sha256_file(path)?;
+    if actual != expected {
+        let _ = fs::remove_file(path);
+        return Err(format!(
+            "Checksum mismatch for {}: expected {}, got {}. The download was deleted.",
+            path.display(),
+            expected,
+            actual
+        )
+        .into());
+    }
+    println!("Checksum verified: {}", path.display());
+    Ok(())
+}
+
+// Lines of sha256sum formatted content, as (file name, checksum)
+fn parse_checksum_lines(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (checksum, name) = line.split_once(char::is_whitespace)?;
+            Some((
+                name.trim().trim_start_matches('*').to_string(),
+                checksum.to_string(),
+            ))
+        })
+        .collect()
+}
+
+// Check a file against its trusted checksum, leaving it in place
+pub fn verify_file(path: &Path, expected: &str) -> io::Result<Verification> {
+    let actual = sha256_file(path)?;
+    if actual == expected {
+        Ok(Verification::Verified)
+    } else {
+        Ok(Verification::Mismatch {
+            expected: expected.to_string(),
+            actual,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        let dir = std::env::temp_dir().join(format!("thumed_checksum_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let binary = dir.join("kubectl");
+        fs::write(&binary, "abc").unwrap();
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(sha256_file(&binary).unwrap(), abc);
+
+        assert_eq!(
+            parse_checksum(&format!(
+                "{}  helm-v3.12.3-linux-amd64.tar.gz\n",
+                abc.to_uppercase()
+            )),
+            Some(abc.to_string())
+        );
+        assert_eq!(parse_checksum("<html>Not Found</html>"), None);
+
+        let trusted = format!(
+            "# campus mirror\n{}  kubectl-v1.28.4-linux-amd64\nnot-a-checksum *helm-v3.12.3-linux-amd64.tar.gz\n",
+            abc
+        );
+        assert_eq!(
+            find_checksum(&trusted, "kubectl-v1.28.4-linux-amd64"),
+            Some(abc.to_string())
+        );
+        assert_eq!(
+            find_checksum(&trusted, "helm-v3.12.3-linux-amd64.tar.gz"),
+            None
+        );
+        assert_eq!(find_checksum(&trusted, "kubectl-v1.28.4-linux-arm64"), None);
+
+        assert_eq!(verify_file(&binary, abc).unwrap(), Verification::Verified);
+        fs::write(&binary, "abd").unwrap();
+        assert!(matches!(
+            verify_file(&binary, abc).unwrap(),
+            Verification::Mismatch { .. }
+        ));
+        assert!(binary.exists());
+
+        assert!(verify_download(&binary, abc).is_err());
+        assert!(!binary.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,4 +1,9 @@
+use crate::backup::BackupTarget;
+use crate::constants;
+use crate::tools::Tool;
 use clap::{Parser, Subcommand};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 
 /// THU Med Login Helper CLI
 #[derive(Parser)]
@@ -8,6 +13,10 @@ pub struct Cli {
     #[arg(short, long)]
     pub interactive: bool,
 
+    /// Hosts file to edit instead of the system's (also THUMED_HOSTS_FILE)
+    #[arg(long, global = true)]
+    pub hosts_file: Option<String>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -15,7 +24,12 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Commands {
     /// Initialize or check environment and tools
-    CheckEnv,
+    CheckEnv {
+        /// Install kubectl and helm from a bundle directory or .tar.gz made
+        /// with `bundle` instead of downloading them
+        #[arg(long)]
+        offline_bundle: Option<PathBuf>,
+    },
     
     /// List pods and website addresses
     ListPods,
@@ -33,6 +47,26 @@ pub enum Commands {
         /// Memory in GB (default: 50)
         #[arg(short, long)]
         memory: Option<u8>,
+
+        /// Resource preset: small, medium, large or default (overridden by --cpu/--memory)
+        #[arg(short, long)]
+        preset: Option<String>,
+
+        /// Login entrypoint recorded for this pod (e.g. /cmd.sh, bash)
+        #[arg(short, long)]
+        login_shell: Option<String>,
+
+        /// Also check that a node has enough allocatable capacity
+        #[arg(long)]
+        check_nodes: bool,
+
+        /// Install even when the namespace quota cannot fit the request
+        #[arg(short, long)]
+        force: bool,
+
+        /// Lifetime of the pod, e.g. 14d or 12h (uninstalled by `reap` afterwards)
+        #[arg(short, long)]
+        ttl: Option<String>,
     },
     
     /// Login to a pod in the terminal
@@ -40,6 +74,32 @@ pub enum Commands {
         /// Pod name to login to
         #[arg(short, long)]
         name: Option<String>,
+
+        /// Shell or script to start instead of the configured entrypoint
+        #[arg(short, long)]
+        shell: Option<String>,
+
+        /// Container to login to in multi-container pods
+        #[arg(short, long)]
+        container: Option<String>,
+    },
+    
+    /// Run a one-off command in a pod
+    Exec {
+        /// Pod name to run the command in
+        pod: String,
+
+        /// Run the command in your personal data directory
+        #[arg(short, long)]
+        personal_dir: bool,
+
+        /// Container to run the command in
+        #[arg(short, long)]
+        container: Option<String>,
+
+        /// Command and arguments to run (after `--`)
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
     },
     
     /// Uninstall a pod
@@ -47,8 +107,273 @@ pub enum Commands {
         /// Pod name to uninstall
         #[arg(short, long)]
         name: Option<String>,
+
+        /// Delete the pod's values file instead of archiving it
+        #[arg(short, long)]
+        delete_config: bool,
+
+        /// Back up the workspace before uninstalling (asks when omitted)
+        #[arg(short, long, value_enum)]
+        backup: Option<BackupTarget>,
+
+        /// Paths to back up: home, rlib, conda (default: all)
+        #[arg(long, value_delimiter = ',', requires = "backup")]
+        backup_paths: Vec<String>,
+
+        /// Uninstall without backing up
+        #[arg(long, conflicts_with = "backup")]
+        no_backup: bool,
+    },
+
+    /// Restore a workspace backup into a pod
+    Restore {
+        /// Pod to restore into
+        #[arg(short, long)]
+        name: String,
+
+        /// Archive to unpack (local file or name in backups/)
+        #[arg(short, long)]
+        archive: String,
+
+        /// Read the archive from the pod's personal NFS directory instead
+        #[arg(short, long)]
+        remote: bool,
+    },
+
+    /// Show CPU and memory usage of pods against their limits
+    Top {
+        /// Only show this pod or release
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Refresh periodically and suggest presets for oversized pods
+        #[arg(short, long)]
+        watch: bool,
+
+        /// Refresh interval in seconds
+        #[arg(short, long, default_value_t = 5)]
+        interval: u64,
+    },
+
+    /// Show the revisions of a pod's helm release
+    History {
+        /// Pod or release name
+        #[arg(short, long)]
+        name: String,
+    },
+
+    /// Roll a pod's helm release back to an earlier revision
+    Rollback {
+        /// Pod or release name
+        #[arg(short, long)]
+        name: String,
+
+        /// Revision to roll back to (asks when omitted)
+        #[arg(short, long)]
+        revision: Option<u32>,
+    },
+
+    /// Push out the expiry of a pod installed with --ttl
+    Extend {
+        /// Pod or release name
+        #[arg(short, long)]
+        name: String,
+
+        /// Time to add, e.g. 7d
+        #[arg(short, long)]
+        by: String,
+    },
+
+    /// Uninstall expired pods installed by this tool
+    Reap {
+        /// Only list the expired pods
+        #[arg(short, long)]
+        dry_run: bool,
+
+        /// Uninstall without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Manage the hosts file entries of this tool
+    Hosts {
+        #[command(subcommand)]
+        action: HostsAction,
+    },
+
+    /// Resolve pod websites with a local DNS stub instead of the hosts file
+    Dns {
+        #[command(subcommand)]
+        action: DnsAction,
+    },
+
+    /// Reach pod websites through a proxy auto-config (PAC) file, without admin rights
+    Pac {
+        #[command(subcommand)]
+        action: PacAction,
+    },
+
+    /// List, install, upgrade and pin the kubectl and helm versions in bin/
+    Tools {
+        #[command(subcommand)]
+        action: ToolsAction,
+    },
+
+    /// Download kubectl and helm into a bundle for offline installation
+    Bundle {
+        /// Bundle directory, or archive when ending with .tar.gz
+        output: PathBuf,
+
+        /// Comma separated <os>/<arch> targets, e.g. linux/amd64,windows/amd64
+        /// (default: this machine)
+        #[arg(short, long, value_delimiter = ',')]
+        targets: Vec<String>,
+    },
+
+    /// Import, fetch and show the kubeconfig used to reach the cluster
+    Kubeconfig {
+        #[command(subcommand)]
+        action: KubeconfigAction,
+    },
+
+    /// Remove hosts entries and values files of pods that no longer exist
+    Cleanup {
+        /// Remove without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Remove every hosts file entry of this tool (e.g. before removing the tool)
+        #[arg(long)]
+        hosts_block: bool,
     },
     
     /// Update user information
     UpdateUser,
-}
\ No newline at end of file
+}
+#[derive(Subcommand)]
+pub enum HostsAction {
+    /// List the hosts entries added by this tool
+    List,
+
+    /// Add the website hostname of a pod, or update its IP
+    Add {
+        /// Pod or release name
+        pod: String,
+
+        /// IP address to point the hostname at (default: the cluster server)
+        #[arg(long)]
+        ip: Option<IpAddr>,
+
+        /// Replace an existing entry that was not added by this tool
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Remove the website hostname of a pod
+    Remove {
+        /// Pod or release name
+        pod: String,
+    },
+
+    /// Match the hosts entries with your current pods and their ingress hostnames
+    Sync,
+
+    /// Restore the hosts file from a backup
+    Restore {
+        /// Backup number, file name or path (lists the backups when omitted)
+        backup: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DnsAction {
+    /// Run the DNS stub answering the website domain with the server address
+    Serve {
+        /// Address to listen on
+        #[arg(short, long, default_value = constants::DNS_LISTEN_ADDR)]
+        listen: SocketAddr,
+
+        /// DNS server for other names (default: the system's nameserver)
+        #[arg(short, long)]
+        upstream: Option<SocketAddr>,
+    },
+
+    /// Show how to point systemd-resolved at the DNS stub for the website domain
+    Setup {
+        /// Address the DNS stub listens on
+        #[arg(short, long, default_value = constants::DNS_LISTEN_ADDR)]
+        listen: SocketAddr,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PacAction {
+    /// Print or save a PAC file sending the website domain through the forwarding proxy
+    Generate {
+        /// Address of the forwarding proxy
+        #[arg(short, long, default_value = constants::PAC_LISTEN_ADDR)]
+        proxy: SocketAddr,
+
+        /// File to write the PAC file to (default: print it)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Run the forwarding proxy to the server, also serving the PAC file at /proxy.pac
+    Serve {
+        /// Address to listen on
+        #[arg(short, long, default_value = constants::PAC_LISTEN_ADDR)]
+        listen: SocketAddr,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ToolsAction {
+    /// Show the versions in use, configured, pinned and installed
+    List,
+
+    /// Install a version next to the other ones, without using it
+    Install {
+        tool: Tool,
+
+        /// Version to install (default: the configured one)
+        version: Option<String>,
+    },
+
+    /// Switch tools to their pinned or configured version
+    Upgrade {
+        /// Tool to upgrade (default: all)
+        tool: Option<Tool>,
+    },
+
+    /// Pin a tool to a version and use it, or remove the pin when no version is given
+    Pin {
+        tool: Tool,
+
+        /// Version to pin
+        version: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum KubeconfigAction {
+    /// Store a copy of a kubeconfig file for this tool to use
+    Import {
+        /// Kubeconfig file to import
+        path: PathBuf,
+    },
+
+    /// Download your kubeconfig from the cluster with the saved credentials
+    Fetch {
+        /// HTTPS endpoint to download from (default: $THUMED_KUBECONFIG_URL)
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Send the credentials to a plain http:// endpoint, unencrypted
+        #[arg(long)]
+        allow_http: bool,
+    },
+
+    /// Show the current cluster, user and credential expiry
+    Show,
+}
@@ -1,18 +1,97 @@
 // Constants module for THU Med Login Helper
 
+use std::net::{IpAddr, Ipv4Addr};
+
 // Default values for pod configuration
 pub const DEFAULT_CPU_CORES: u8 = 32;
 pub const DEFAULT_MEMORY_GB: u8 = 50;
 
+// Resource presets: (name, CPU cores, memory in GB), smallest first
+pub const RESOURCE_PRESETS: &[(&str, u8, u8)] = &[
+    ("small", 4, 8),
+    ("medium", 8, 16),
+    ("large", 16, 32),
+    ("default", DEFAULT_CPU_CORES, DEFAULT_MEMORY_GB),
+];
+
 // Server address and URLs
-pub const SERVER_IP: &str = "166.111.153.65";
+pub const SERVER_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(166, 111, 153, 65));
 pub const HELM_REPO_URL: &str = "http://166.111.153.65:7001";
 pub const WEBSITE_DOMAIN: &str = "apps.med.thu";
 
+// Markers of the hosts file block owned by this tool
+pub const HOSTS_BLOCK_BEGIN: &str = "# BEGIN thumed_login";
+pub const HOSTS_BLOCK_END: &str = "# END thumed_login";
+// Environment variable overriding the hosts file path
+pub const HOSTS_FILE_ENV: &str = "THUMED_HOSTS_FILE";
+// Environment variable naming the command used to elevate hosts file writes
+pub const ELEVATOR_ENV: &str = "THUMED_ELEVATOR";
+// Number of hosts file backups to keep
+pub const HOSTS_BACKUP_RETENTION: usize = 10;
+// Comment marking hosts file entries added by earlier versions of this tool
+pub const HOSTS_ENTRY_COMMENT: &str = "Added by thumed_login";
+
+// Default listen address of the local DNS stub (port 53 is usually taken by
+// the system resolver)
+pub const DNS_LISTEN_ADDR: &str = "127.0.0.1:5335";
+// systemd-resolved drop-in routing the website domain to the DNS stub
+pub const RESOLVED_DROP_IN: &str = "/etc/systemd/resolved.conf.d/thumed-login.conf";
+
+// Default listen address of the local forwarding proxy serving the PAC file
+pub const PAC_LISTEN_ADDR: &str = "127.0.0.1:7070";
+
+// Annotations recorded on the helm release secrets of pods installed by this tool
+pub const MANAGED_BY_ANNOTATION: &str = "thumed-login/managed-by";
+pub const EXPIRES_AT_ANNOTATION: &str = "thumed-login/expires-at";
+pub const MANAGED_BY_VALUE: &str = "thumed_login";
+
+// Pod paths that can be backed up before uninstalling: (name, path)
+pub const BACKUP_PATHS: &[(&str, &str)] = &[
+    ("home", "$HOME"),
+    ("rlib", "/usr/local/lib/R/site-library"),
+    ("conda", "/opt/conda/envs"),
+];
+
+// Pod image and login entrypoints
+pub const DEFAULT_IMAGE_REPOSITORY: &str = "base.med.thu/public/rstudio";
+// Login entrypoint per image repository
+pub const IMAGE_LOGIN_SHELLS: &[(&str, &str)] = &[(DEFAULT_IMAGE_REPOSITORY, "/cmd.sh")];
+// Entrypoints probed in order when the configured one is not available
+pub const LOGIN_SHELL_CANDIDATES: &[&str] = &["/cmd.sh", "bash", "zsh", "sh"];
+
 // Tool version
 pub const KUBECTL_VERSION: &str = "v1.28.4";
 pub const HELM_VERSION: &str = "v3.12.3";
 
+// Environment variables with the path of a kubectl or helm binary to use
+// instead of the ones in bin/
+pub const KUBECTL_PATH_ENV: &str = "THUMED_KUBECTL";
+pub const HELM_PATH_ENV: &str = "THUMED_HELM";
+
+// Official download sources, tried in order. Comma separated lists of mirrors
+// following the same layout in the environment variables replace them for the
+// downloads, but checksums are only ever fetched from these HTTPS sources.
+pub const KUBECTL_MIRRORS: &[&str] = &["https://dl.k8s.io/release"];
+pub const HELM_MIRRORS: &[&str] = &["https://get.helm.sh"];
+pub const KUBECTL_MIRRORS_ENV: &str = "THUMED_KUBECTL_MIRRORS";
+pub const HELM_MIRRORS_ENV: &str = "THUMED_HELM_MIRRORS";
+
+// SHA-256 checksums pinned per release artifact, e.g. ("kubectl-v1.28.4-linux-amd64", "<sha256>")
+// or ("helm-v3.12.3-linux-amd64.tar.gz", "<sha256>"). Artifacts without a pinned
+// checksum are looked up in the trusted checksums file, then checked against the
+// .sha256 files of the official sources, and are not installed when neither
+// has them.
+pub const PINNED_CHECKSUMS: &[(&str, &str)] = &[];
+// Environment variable with the path of a trusted checksums file, in sha256sum
+// format ("<sha256>  <artifact>"), for networks where the official sources
+// cannot be reached. Defaults to config/trusted_checksums.sha256.
+pub const TRUSTED_CHECKSUMS_ENV: &str = "THUMED_TRUSTED_CHECKSUMS";
+
+// Environment variable with the endpoint serving the kubeconfig of a user,
+// used by `kubeconfig fetch` when no --url is given. There is no default: the
+// cluster administrators provide it.
+pub const KUBECONFIG_URL_ENV: &str = "THUMED_KUBECONFIG_URL";
+
 // Helm repositories
 pub const HELM_REPO_NAME: &str = "med-helm";
 
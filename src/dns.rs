@@ -0,0 +1,274 @@
+use crate::constants;
+use crate::hosts_manager;
+use std::error::Error;
+use std::fs;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+// DNS record types and classes used by the stub
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+// TTL of the answers for the website domain, in seconds
+const ANSWER_TTL: u32 = 60;
+const HEADER_LEN: usize = 12;
+
+/// The question of a DNS query
+#[derive(Debug, PartialEq, Eq)]
+struct Question {
+    name: String,
+    qtype: u16,
+    qclass: u16,
+    // Offset of the end of the question section in the packet
+    end: usize,
+}
+
+/// Parse the first question of a query, None for packets that are not a
+/// standard query with a single question
+fn parse_query(packet: &[u8]) -> Option<Question> {
+    if packet.len() < HEADER_LEN {
+        return None;
+    }
+    let flags = u16::from_be_bytes([packet[2], packet[3]]);
+    let qdcount = u16::from_be_bytes([packet[4], packet[5]]);
+    // Responses and opcodes other than QUERY are not handled
+    if flags & 0x8000 != 0 || (flags >> 11) & 0xf != 0 || qdcount != 1 {
+        return None;
+    }
+
+    let mut labels = Vec::new();
+    let mut pos = HEADER_LEN;
+    loop {
+        let len = *packet.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        // Compression pointers are not expected in a question
+        if len > 63 {
+            return None;
+        }
+        let label = packet.get(pos..pos + len)?;
+        labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+        pos += len;
+    }
+    let fixed = packet.get(pos..pos + 4)?;
+    Some(Question {
+        name: labels.join("."),
+        qtype: u16::from_be_bytes([fixed[0], fixed[1]]),
+        qclass: u16::from_be_bytes([fixed[2], fixed[3]]),
+        end: pos + 4,
+    })
+}
+
+/// Build the authoritative answer to a query for the website domain: an
+/// address record pointing at `ip`, or no records when the query asks for
+/// another type
+fn build_answer(query: &[u8], question: &Question, ip: IpAddr) -> Vec<u8> {
+    let (rtype, rdata) = match ip {
+        IpAddr::V4(ip) => (TYPE_A, ip.octets().to_vec()),
+        IpAddr::V6(ip) => (TYPE_AAAA, ip.octets().to_vec()),
+    };
+    let answers =
+        question.qclass == CLASS_IN && (question.qtype == rtype || question.qtype == TYPE_ANY);
+
+    // Response, authoritative, recursion available, copying the opcode and
+    // recursion desired bits of the query
+    let query_flags = u16::from_be_bytes([query[2], query[3]]);
+    let flags = 0x8000 | 0x0400 | 0x0080 | (query_flags & 0x7900);
+
+    let mut response = Vec::with_capacity(question.end + 16 + rdata.len());
+    response.extend_from_slice(&query[0..2]);
+    response.extend_from_slice(&flags.to_be_bytes());
+    response.extend_from_slice(&1u16.to_be_bytes());
+    response.extend_from_slice(&u16::from(answers).to_be_bytes());
+    response.extend_from_slice(&[0, 0, 0, 0]);
+    response.extend_from_slice(&query[HEADER_LEN..question.end]);
+    if answers {
+        // Name as a pointer to the question
+        response.extend_from_slice(&[0xc0, HEADER_LEN as u8]);
+        response.extend_from_slice(&rtype.to_be_bytes());
+        response.extend_from_slice(&CLASS_IN.to_be_bytes());
+        response.extend_from_slice(&ANSWER_TTL.to_be_bytes());
+        response.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        response.extend_from_slice(&rdata);
+    }
+    response
+}
+
+/// Send a query to the upstream server and return its response
+fn forward(query: &[u8], upstream: SocketAddr) -> std::io::Result<Vec<u8>> {
+    let bind: SocketAddr = if upstream.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(bind)?;
+    socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+    socket.connect(upstream)?;
+    socket.send(query)?;
+    let mut buf = [0u8; 4096];
+    let len = socket.recv(&mut buf)?;
+    Ok(buf[..len].to_vec())
+}
+
+/// First non-loopback nameserver of the system, read from the file listing
+/// the real upstream servers of systemd-resolved and then /etc/resolv.conf
+pub fn system_upstream() -> Option<SocketAddr> {
+    ["/run/systemd/resolve/resolv.conf", "/etc/resolv.conf"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("nameserver"))
+                .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+                .collect::<Vec<_>>()
+        })
+        .find(|ip| !ip.is_loopback())
+        .map(|ip| SocketAddr::new(ip, 53))
+}
+
+// Run the DNS stub: answer the website domain with the server address and
+// forward every other query to `upstream`
+pub fn serve(listen: SocketAddr, upstream: Option<SocketAddr>) -> Result<(), Box<dyn Error>> {
+    let upstream = match upstream.or_else(system_upstream) {
+        Some(upstream) => upstream,
+        None => {
+            eprintln!("No upstream DNS server found, pass one with --upstream");
+            return Err("No upstream DNS server".into());
+        }
+    };
+    let socket = UdpSocket::bind(listen).map_err(|e| {
+        eprintln!("Failed to listen on {}: {}", listen, e);
+        e
+    })?;
+    println!(
+        "Answering *.{} with {} on {}, forwarding other queries to {}",
+        constants::WEBSITE_DOMAIN,
+        constants::SERVER_IP,
+        listen,
+        upstream
+    );
+    println!("Press Ctrl+C to stop.");
+
+    let mut buf = [0u8; 4096];
+    loop {
+        let (len, client) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("Failed to receive a query: {}", e);
+                continue;
+            }
+        };
+        let query = buf[..len].to_vec();
+        match parse_query(&query) {
+            Some(question) if hosts_manager::is_website_hostname(&question.name) => {
+                let response = build_answer(&query, &question, constants::SERVER_IP);
+                if let Err(e) = socket.send_to(&response, client) {
+                    eprintln!("Failed to answer {}: {}", client, e);
+                }
+            }
+            _ => {
+                // Forward in the background so a slow upstream does not hold
+                // back the local answers
+                let socket = socket.try_clone()?;
+                thread::spawn(move || match forward(&query, upstream) {
+                    Ok(response) => {
+                        let _ = socket.send_to(&response, client);
+                    }
+                    Err(e) => eprintln!("Failed to forward a query to {}: {}", upstream, e),
+                });
+            }
+        }
+    }
+}
+
+// Print how to make systemd-resolved send the website domain to the stub
+pub fn print_setup(listen: SocketAddr) {
+    let drop_in = format!(
+        "[Resolve]\nDNS={}\nDomains=~{}\n",
+        listen,
+        constants::WEBSITE_DOMAIN
+    );
+    println!(
+        "To resolve *.{} through the local DNS stub with systemd-resolved:",
+        constants::WEBSITE_DOMAIN
+    );
+    println!();
+    println!("1. Create {} with:", constants::RESOLVED_DROP_IN);
+    println!();
+    for line in drop_in.lines() {
+        println!("   {}", line);
+    }
+    println!();
+    println!("   e.g. in one command:");
+    println!(
+        "   sudo mkdir -p {} && printf '{}' | sudo tee {}",
+        std::path::Path::new(constants::RESOLVED_DROP_IN)
+            .parent()
+            .unwrap_or(std::path::Path::new("/"))
+            .display(),
+        drop_in.replace('\n', "\\n"),
+        constants::RESOLVED_DROP_IN
+    );
+    println!();
+    println!("2. Restart systemd-resolved: sudo systemctl restart systemd-resolved");
+    println!(
+        "3. Keep the stub running: thumed_login dns serve --listen {}",
+        listen
+    );
+    println!();
+    println!(
+        "Only queries for {} go to the stub, other names are resolved as before.",
+        constants::WEBSITE_DOMAIN
+    );
+    println!("Ports other than 53 in DNS= need systemd 246 or newer.");
+    println!("To undo, delete the file and restart systemd-resolved.");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Query for `name` as sent by dig, with recursion desired
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut packet = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.push(0);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn test_answer_local_names() {
+        let packet = query("Demo.apps.med.thu", TYPE_A);
+        let question = parse_query(&packet).unwrap();
+        assert_eq!(question.name, "demo.apps.med.thu");
+        assert_eq!(question.end, packet.len());
+        assert!(hosts_manager::is_website_hostname(&question.name));
+        assert!(!hosts_manager::is_website_hostname("notapps.med.thu"));
+        assert!(!hosts_manager::is_website_hostname("example.com"));
+
+        let response = build_answer(&packet, &question, constants::SERVER_IP);
+        assert_eq!(&response[0..2], &[0x12, 0x34]);
+        assert_eq!(&response[2..4], &[0x85, 0x80]);
+        assert_eq!(&response[6..8], &[0, 1]);
+        assert_eq!(&response[response.len() - 4..], &[166, 111, 153, 65]);
+
+        // Other record types get an empty answer
+        let packet = query("demo.apps.med.thu", TYPE_AAAA);
+        let question = parse_query(&packet).unwrap();
+        let response = build_answer(&packet, &question, constants::SERVER_IP);
+        assert_eq!(&response[6..8], &[0, 0]);
+        assert_eq!(response.len(), packet.len());
+
+        assert!(parse_query(&packet[..14]).is_none());
+    }
+}
@@ -0,0 +1,406 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+// Size of the chunks copied between progress updates
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Downloads files over HTTP(S) with resume, retries and proxy support
+pub struct Downloader {
+    connect_timeout: Duration,
+    read_timeout: Duration,
+    retries: u32,
+    backoff: Duration,
+    progress: bool,
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Downloader {
+            connect_timeout: Duration::from_secs(15),
+            read_timeout: Duration::from_secs(60),
+            retries: 3,
+            backoff: Duration::from_secs(2),
+            progress: io::stderr().is_terminal(),
+        }
+    }
+}
+
+/// Response with an error status, kept typed so callers can match on the code
+#[derive(Debug)]
+pub struct HttpError {
+    pub code: u16,
+    status_text: String,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HTTP {} {}", self.code, self.status_text)
+    }
+}
+
+impl Error for HttpError {}
+
+/// Why an attempt failed, and whether trying again may help
+struct AttemptError {
+    error: Box<dyn Error>,
+    /// HTTP status of the response, when the server answered with an error
+    status: Option<u16>,
+    retry: bool,
+}
+
+impl<E: Into<Box<dyn Error>>> From<E> for AttemptError {
+    fn from(error: E) -> Self {
+        AttemptError {
+            error: error.into(),
+            status: None,
+            retry: true,
+        }
+    }
+}
+
+impl Downloader {
+    fn agent(&self, url: &str) -> Result<ureq::Agent, Box<dyn Error>> {
+        let mut builder = ureq::AgentBuilder::new()
+            .timeout_connect(self.connect_timeout)
+            .timeout_read(self.read_timeout);
+        if let Some(proxy) = proxy_for(url, |name| std::env::var(name).ok()) {
+            builder = builder.proxy(ureq::Proxy::new(&proxy)?);
+        }
+        Ok(builder.build())
+    }
+
+    /// Run `attempt` until it succeeds, fails for good or runs out of retries,
+    /// waiting twice as long before each new attempt
+    fn with_retry<T>(
+        &self,
+        url: &str,
+        mut attempt: impl FnMut() -> Result<T, AttemptError>,
+    ) -> Result<T, Box<dyn Error>> {
+        let mut delay = self.backoff;
+        let mut tries = 0;
+        loop {
+            match attempt() {
+                Ok(value) => return Ok(value),
+                Err(e) if e.retry && tries < self.retries => {
+                    tries += 1;
+                    eprintln!(
+                        "Download of {} failed: {}. Retrying in {}s ({}/{})...",
+                        url,
+                        e.error,
+                        delay.as_secs_f32(),
+                        tries,
+                        self.retries
+                    );
+                    thread::sleep(delay);
+                    delay *= 2;
+                }
+                Err(e) => return Err(e.error),
+            }
+        }
+    }
+
+    /// Send a GET request, with a Range header when resuming from `offset` and
+    /// basic authentication when `credentials` are given
+    fn get(
+        &self,
+        url: &str,
+        offset: u64,
+        credentials: Option<(&str, &str)>,
+    ) -> Result<ureq::Response, AttemptError> {
+        let mut request = self.agent(url)?.get(url);
+        if offset > 0 {
+            request = request.set("Range", &format!("bytes={}-", offset));
+        }
+        if let Some((user, password)) = credentials {
+            let token = STANDARD.encode(format!("{}:{}", user, password));
+            request = request.set("Authorization", &format!("Basic {}", token));
+        }
+        match request.call() {
+            Ok(response) => Ok(response),
+            // Server errors, timeouts and rate limits may go away, other
+            // client errors will not
+            Err(ureq::Error::Status(code, response)) => Err(AttemptError {
+                error: Box::new(HttpError {
+                    code,
+                    status_text: response.status_text().to_string(),
+                }),
+                status: Some(code),
+                retry: code >= 500 || code == 408 || code == 429 || code == 416,
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Download `url` to `path`. Data is written to `<path>.part` first, so an
+    /// interrupted download is resumed from where it stopped.
+    pub fn download(&self, url: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+        let part = part_path(path);
+        self.with_retry(url, || self.download_attempt(url, &part))?;
+        fs::rename(&part, path)?;
+        Ok(())
+    }
+
+    fn download_attempt(&self, url: &str, part: &Path) -> Result<(), AttemptError> {
+        let offset = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+        let response = match self.get(url, offset, None) {
+            // The partial file is already complete or no longer matches: start over
+            Err(e) if offset > 0 && e.status == Some(416) => {
+                fs::remove_file(part)?;
+                return Err(e);
+            }
+            result => result?,
+        };
+
+        // 206 continues the partial file, anything else is the whole file
+        let resumed = offset > 0 && response.status() == 206;
+        let (mut file, start) = if resumed {
+            (OpenOptions::new().append(true).open(part)?, offset)
+        } else {
+            (File::create(part)?, 0)
+        };
+        let total = response
+            .header("Content-Length")
+            .and_then(|len| len.parse::<u64>().ok())
+            .map(|len| len + start);
+
+        let name = part
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .trim_end_matches(".part")
+            .to_string();
+        let mut reader = response.into_reader();
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        let mut done = start;
+        loop {
+            let len = reader.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            file.write_all(&buf[..len])?;
+            done += len as u64;
+            if self.progress {
+                print_progress(&name, done, total);
+            }
+        }
+        file.flush()?;
+        if self.progress {
+            eprintln!();
+        }
+        if let Some(total) = total {
+            if done < total {
+                return Err(format!("Connection closed after {} of {} bytes", done, total).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch a small text file such as a checksum file
+    pub fn fetch_text(&self, url: &str) -> Result<String, Box<dyn Error>> {
+        self.with_retry(url, || Ok(self.get(url, 0, None)?.into_string()?))
+    }
+
+    /// Fetch a small text file that requires HTTP basic authentication
+    pub fn fetch_text_as(
+        &self,
+        url: &str,
+        user: &str,
+        password: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        self.with_retry(url, || {
+            Ok(self.get(url, 0, Some((user, password)))?.into_string()?)
+        })
+    }
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Print a one-line progress bar to stderr
+fn print_progress(name: &str, done: u64, total: Option<u64>) {
+    const MIB: f64 = 1024.0 * 1024.0;
+    match total {
+        Some(total) if total > 0 => {
+            let ratio = (done as f64 / total as f64).min(1.0);
+            let filled = (ratio * 30.0) as usize;
+            eprint!(
+                "\r{} [{}{}] {:>3}% {:.1}/{:.1} MiB",
+                name,
+                "#".repeat(filled),
+                " ".repeat(30 - filled),
+                (ratio * 100.0) as u32,
+                done as f64 / MIB,
+                total as f64 / MIB
+            );
+        }
+        _ => eprint!("\r{} {:.1} MiB", name, done as f64 / MIB),
+    }
+}
+
+/// Proxy to use for `url` according to the HTTP_PROXY, HTTPS_PROXY, ALL_PROXY
+/// and NO_PROXY environment variables (upper or lower case), read with `var`
+fn proxy_for(url: &str, var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let env = |name: &str| {
+        var(&name.to_ascii_lowercase())
+            .or_else(|| var(name))
+            .filter(|value| !value.is_empty())
+    };
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit('@').next()?;
+    let host = match authority.strip_prefix('[') {
+        Some(v6) => v6.split(']').next()?,
+        None => authority.split(':').next()?,
+    }
+    .to_ascii_lowercase();
+
+    if let Some(no_proxy) = env("NO_PROXY") {
+        for pattern in no_proxy.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            if pattern == "*" {
+                return None;
+            }
+            let pattern = pattern.trim_start_matches("*.").trim_start_matches('.');
+            let pattern = match pattern.rsplit_once(':') {
+                Some((domain, port)) if port.chars().all(|c| c.is_ascii_digit()) => domain,
+                _ => pattern,
+            }
+            .to_ascii_lowercase();
+            if host == pattern || host.ends_with(&format!(".{}", pattern)) {
+                return None;
+            }
+        }
+    }
+
+    let proxy = match scheme {
+        "https" => env("HTTPS_PROXY"),
+        "http" => env("HTTP_PROXY"),
+        _ => None,
+    };
+    proxy.or_else(|| env("ALL_PROXY"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+
+    // Serve one response per connection: `responses` are (status, body) pairs
+    // and a Range request gets the requested part of the body with a 206
+    fn serve(responses: Vec<(u16, &'static [u8])>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/kubectl", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut head = Vec::new();
+                let mut byte = [0u8; 1];
+                while !head.ends_with(b"\r\n\r\n") {
+                    stream.read_exact(&mut byte).unwrap();
+                    head.push(byte[0]);
+                }
+                let head = String::from_utf8(head).unwrap();
+                let range = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("Range: bytes="))
+                    .and_then(|r| r.trim_end_matches('-').parse::<usize>().ok());
+                let (status, body) = match range {
+                    Some(start) if status == 200 => (206, &body[start..]),
+                    _ => (status, body),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+                requests.push(head);
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn downloader() -> Downloader {
+        Downloader {
+            retries: 2,
+            backoff: Duration::from_millis(10),
+            progress: false,
+            ..Downloader::default()
+        }
+    }
+
+    #[test]
+    fn test_download_resume_and_retry() {
+        let dir = std::env::temp_dir().join(format!("thumed_download_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("kubectl");
+        let body: &[u8] = b"0123456789abcdef";
+
+        // A server error is retried, and the partial file is resumed
+        fs::write(part_path(&path), &body[..6]).unwrap();
+        let (url, server) = serve(vec![(503, b"busy"), (200, body)]);
+        downloader().download(&url, &path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), body);
+        assert!(!part_path(&path).exists());
+        let requests = server.join().unwrap();
+        assert!(requests[1].contains("Range: bytes=6-"));
+
+        // A missing file is not retried
+        let (url, server) = serve(vec![(404, b"not found")]);
+        assert!(downloader().download(&url, &dir.join("helm")).is_err());
+        assert_eq!(server.join().unwrap().len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_proxy_for() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        let vars = env(&[
+            ("https_proxy", "http://proxy:3128"),
+            ("HTTP_PROXY", "http://proxy:8080"),
+            ("NO_PROXY", "localhost,.med.thu,10.0.0.1:80"),
+        ]);
+        assert_eq!(
+            proxy_for("https://dl.k8s.io/release/stable.txt", vars),
+            Some("http://proxy:3128".to_string())
+        );
+        assert_eq!(
+            proxy_for("http://get.helm.sh/x", vars),
+            Some("http://proxy:8080".to_string())
+        );
+        assert_eq!(proxy_for("http://base.med.thu/x", vars), None);
+        assert_eq!(proxy_for("http://10.0.0.1:7001/x", vars), None);
+        assert_eq!(proxy_for("http://localhost:8000", vars), None);
+        assert_eq!(
+            proxy_for("https://x", env(&[("ALL_PROXY", "socks5://p:1080")])),
+            Some("socks5://p:1080".to_string())
+        );
+        assert_eq!(
+            proxy_for(
+                "https://x",
+                env(&[("no_proxy", "*"), ("ALL_PROXY", "http://p")])
+            ),
+            None
+        );
+    }
+}
@@ -1,9 +1,10 @@
-use crate::{constants, platform, utils};
+use crate::tools::{self, Tool};
+use crate::{bundle, checksum, constants, interaction, kubeconfig, platform, utils};
 use std::env;
 use std::error::Error;
 use std::fs::File;
-use std::io::{self, Read, Write};
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 
 pub struct UserInfo {
     pub user: String,
@@ -90,6 +91,7 @@ impl UserInfo {
         Ok((user, password))
     }
 
+    #[allow(clippy::needless_return)]
     pub fn load() -> Result<Self, Box<dyn Error>> {
         let config_path = Self::get_config_path()?;
 
@@ -137,7 +139,43 @@ pub fn add_path(path: &str) -> Result<(), Box<dyn Error>> {
     };
     Ok(())
 }
-pub fn ensure_tools_available() -> Result<(), Box<dyn Error>> {
+// Install the desired version of a tool and use it from bin/
+fn install_tool(bin_dir: &Path, tool: Tool) -> Result<(), Box<dyn Error>> {
+    let version = tools::desired_version(bin_dir, tool);
+    tools::install(bin_dir, tool, &version)?;
+    tools::activate(bin_dir, tool, &version)
+}
+
+// Compare the tools in bin/ with the pinned or configured versions and offer
+// to upgrade the ones that differ
+fn check_tool_versions(bin_dir: &Path) -> Result<(), Box<dyn Error>> {
+    for tool in tools::ALL_TOOLS {
+        let desired = tools::desired_version(bin_dir, tool);
+        let path = platform::get_bin_path(bin_dir, tool.name());
+        let Some(current) = tools::detect_version(tool, &path) else {
+            println!("Could not detect the version of {}", tool.name());
+            continue;
+        };
+        if current == desired {
+            continue;
+        }
+        println!("{} {} is installed, {} is expected", tool.name(), current, desired);
+        if !io::stdin().is_terminal() {
+            println!("Run `tools upgrade {}` to switch to it", tool.name());
+            continue;
+        }
+        if interaction::confirm(&format!("Switch {} to {}?", tool.name(), desired))? {
+            if let Err(e) = tools::upgrade(bin_dir, tool) {
+                println!("Failed to upgrade {}: {}", tool.name(), e);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Make sure kubectl and helm are in bin/, installing them from
+// `offline_bundle` when given instead of downloading them
+pub fn ensure_tools_available(offline_bundle: Option<&Path>) -> Result<(), Box<dyn Error>> {
     let bin_dir = std::env::current_dir()?.join("bin");
 
     if !bin_dir.exists() {
@@ -147,9 +185,48 @@ pub fn ensure_tools_available() -> Result<(), Box<dyn Error>> {
 
     add_path(bin_dir.to_string_lossy().as_ref())?;
 
+    if let Some(bundle) = offline_bundle {
+        println!("Installing tools from offline bundle {}...", bundle.display());
+        bundle::install(bundle, &bin_dir)?;
+    }
+
     let kubectl_path = platform::get_bin_path(&bin_dir, "kubectl");
     let helm_path = platform::get_bin_path(&bin_dir, "helm");
 
+    // Re-verify the binaries already there against the trusted checksums,
+    // setting aside any that do not match so they are fetched again
+    for tool in tools::ALL_TOOLS {
+        let path = platform::get_bin_path(&bin_dir, tool.name());
+        if !path.exists() {
+            continue;
+        }
+        let name = tool.name();
+        let reason = match tools::verify_installed(&bin_dir, tool) {
+            Ok(checksum::Verification::Verified) => {
+                println!("{} checksum verified", name);
+                continue;
+            }
+            Ok(checksum::Verification::Unknown) => {
+                "is not a copy of any version installed by this tool".to_string()
+            }
+            Ok(checksum::Verification::Mismatch { expected, actual }) => format!(
+                "does not match the checksum of its release (expected {}, got {})",
+                expected, actual
+            ),
+            Err(e) => {
+                eprintln!("Could not verify {}: {}", name, e);
+                continue;
+            }
+        };
+        eprintln!(
+            "Warning: {} {}, it may be tampered with or corrupted",
+            name, reason
+        );
+        let rejected = path.with_extension("rejected");
+        std::fs::rename(&path, &rejected)?;
+        eprintln!("Moved it to {}, downloading it again", rejected.display());
+    }
+
     let kubectl_exists = kubectl_path.exists();
     let helm_exists = helm_path.exists();
 
@@ -157,7 +234,7 @@ pub fn ensure_tools_available() -> Result<(), Box<dyn Error>> {
         println!("Some required tools are missing. Will attempt to download them:");
 
         if !kubectl_exists {
-            match utils::download_kubectl(&bin_dir) {
+            match install_tool(&bin_dir, Tool::Kubectl) {
                 Ok(_) => println!("Successfully downloaded kubectl"),
                 Err(e) => println!(
                     "Failed to download kubectl: {}. Please download it manually.",
@@ -167,7 +244,7 @@ pub fn ensure_tools_available() -> Result<(), Box<dyn Error>> {
         }
 
         if !helm_exists {
-            match utils::download_helm(&bin_dir) {
+            match install_tool(&bin_dir, Tool::Helm) {
                 Ok(_) => println!("Successfully downloaded helm"),
                 Err(e) => println!(
                     "Failed to download helm: {}. Please download it manually.",
@@ -177,32 +254,45 @@ pub fn ensure_tools_available() -> Result<(), Box<dyn Error>> {
         }
     } else {
         println!("All required tools found in bin directory.");
-    }
-
-    // Re-check after potential downloads
-    let kubectl_exists = kubectl_path.exists();
-    let helm_exists = helm_path.exists();
-
-    if kubectl_exists {
-        let bin_kubectl = kubectl_path.to_string_lossy().to_string();
-        match utils::run_cmd(&bin_kubectl, &["version", "--client"]) {
-            Ok(_) => println!("kubectl is working correctly"),
-            Err(e) => println!("Warning: kubectl may not be working: {}", e),
+        // Tools from a bundle are used as they are, upgrading needs internet access
+        if offline_bundle.is_none() {
+            check_tool_versions(&bin_dir)?;
         }
-    } else {
-        println!("kubectl is still missing. Please download it manually from:");
-        println!("kubectl: kubernetes.io/docs/tasks/tools/");
     }
 
-    if helm_exists {
-        let bin_helm = helm_path.to_string_lossy().to_string();
-        match utils::run_cmd(&bin_helm, &["version"]) {
-            Ok(_) => println!("helm is working correctly"),
-            Err(e) => println!("Warning: helm may not be working: {}", e),
+    // Report the binaries that will be used, which may come from a
+    // configured path or the system PATH instead of bin/
+    for tool in tools::ALL_TOOLS {
+        if let Ok(path) = env::var(tool.path_env()) {
+            if !Path::new(&path).is_file() {
+                println!(
+                    "Warning: {} is set to {}, which does not exist",
+                    tool.path_env(),
+                    path
+                );
+            }
+        }
+        let Some((path, source)) = tools::locate(tool) else {
+            println!("{} is still missing. Please download it manually from:", tool.name());
+            match tool {
+                Tool::Kubectl => println!("kubectl: kubernetes.io/docs/tasks/tools/"),
+                Tool::Helm => println!("helm: https://github.com/helm/helm/releases"),
+            }
+            continue;
+        };
+        let version = tools::detect_version(tool, &path);
+        println!(
+            "Using {} {} from the {}: {}",
+            tool.name(),
+            version.as_deref().unwrap_or("(unknown version)"),
+            source,
+            path.display()
+        );
+        if version.is_some() {
+            println!("{} is working correctly", tool.name());
+        } else {
+            println!("Warning: {} may not be working", tool.name());
         }
-    } else {
-        println!("helm is still missing. Please download it manually from:");
-        println!("helm: https://github.com/helm/helm/releases");
     }
 
     Ok(())
@@ -210,22 +300,22 @@ pub fn ensure_tools_available() -> Result<(), Box<dyn Error>> {
 
 fn init_helm() -> Result<(), Box<dyn Error>> {
     // Check if med-helm repo already exists
-    let helm_list = utils::run_cmd("helm", &["repo", "list"])?;
+    let helm_list = utils::run_cmd(&tools::helm(), &["repo", "list"])?;
 
     if !helm_list.contains(constants::HELM_REPO_NAME) {
         let _helm_init = utils::run_cmd(
-            "helm",
+            &tools::helm(),
             &["repo", "add", constants::HELM_REPO_NAME, constants::HELM_REPO_URL],
         )?;
         println!("Added {} repository", constants::HELM_REPO_NAME);
     } else {
         println!("{} repository already exists", constants::HELM_REPO_NAME);
     }
-    let helm_update = utils::run_cmd("helm", &["repo", "update"])?;
+    let helm_update = utils::run_cmd(&tools::helm(), &["repo", "update"])?;
     println!("{}", helm_update);
     Ok(())
 }
-pub fn check_env() {
+pub fn check_env(offline_bundle: Option<&Path>) {
     println!("Checking environment...");
     match UserInfo::load() {
         Ok(user_info) => {
@@ -237,10 +327,13 @@ pub fn check_env() {
             return;
         }
     }
-    match ensure_tools_available() {
+    match ensure_tools_available(offline_bundle) {
         Ok(_) => println!("Tool directory setup complete"),
         Err(e) => println!("Error setting up tool directory: {}", e),
     }
+    if !kubeconfig::is_configured() {
+        println!("{}", kubeconfig::MISSING_HINT);
+    }
     match init_helm() {
         Ok(_) => println!("Helm initialized successfully"),
         Err(e) => println!("Error initializing helm: {}", e),
@@ -1,139 +1,169 @@
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
-// use std::path::Path;
+use crate::constants;
+use crate::utils;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HostEntry {
-    pub ip: String,
+    pub ip: IpAddr,
     pub hostnames: Vec<String>,
     pub comment: Option<String>,
 }
 
-pub struct HostsFile {
-    path: String,
-    entries: Vec<HostEntry>,
-}
+impl HostEntry {
+    /// Parse an entry line: None for blank lines and comments, an error
+    /// describing the problem for lines that are not valid entries. Hostnames
+    /// are not validated, so existing entries are always recognized; only user
+    /// input is held to `validate_hostname`.
+    fn parse(line: &str) -> Result<Option<Self>, String> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return Ok(None);
+        }
 
-impl HostsFile {
-    /// Create a new HostsFile instance by loading the system's hosts file
-    pub fn new() -> io::Result<Self> {
-        let path = Self::get_hosts_file_path();
-        let entries = Self::parse_hosts_file(&path)?;
+        // Split off the comment, then the IP address is the first part and
+        // the rest are hostnames
+        let (content, comment) = match trimmed.split_once('#') {
+            Some((content, comment)) => (content, Some(comment.trim().to_string())),
+            None => (trimmed, None),
+        };
+        let mut parts = content.split_whitespace();
+        let ip = parse_ip(parts.next().unwrap_or_default())?;
+        let hostnames: Vec<String> = parts.map(|h| h.to_string()).collect();
+        if hostnames.is_empty() {
+            return Err(format!("no hostname for {}", ip));
+        }
 
-        Ok(Self { path, entries })
+        Ok(Some(HostEntry {
+            ip,
+            hostnames,
+            comment,
+        }))
     }
 
-    /// Get the path to the hosts file based on the operating system
-    fn get_hosts_file_path() -> String {
-        if cfg!(windows) {
-            r"C:\Windows\System32\drivers\etc\hosts".to_string()
-        } else {
-            // Fallback to Unix-like systems
-            "/etc/hosts".to_string()
+    /// Format the entry as a hosts file line (without line ending)
+    fn to_line(&self) -> String {
+        let mut line = format!("{}    {}", self.ip, self.hostnames.join(" "));
+        if let Some(comment) = &self.comment {
+            line = format!("{}  # {}", line, comment);
         }
+        line
     }
+}
 
-    /// Parse the hosts file into a vector of HostEntry
-    fn parse_hosts_file(path: &str) -> io::Result<Vec<HostEntry>> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let mut entries = Vec::new();
-
-        for line in reader.lines() {
-            let line = line?;
-            let trimmed = line.trim();
-
-            // Skip empty lines and comments
-            if trimmed.is_empty() || trimmed.starts_with('#') {
-                continue;
-            }
-
-            // Split the line by whitespace
-            let parts: Vec<&str> = trimmed.split_whitespace().collect();
-            if parts.is_empty() {
-                continue;
-            }
-
-            // The IP address is the first part
-            let ip = parts[0].to_string();
+/// Parse the address of a hosts file entry. IPv6 zone identifiers
+/// (`fe80::1%eth0`) are rejected since resolvers do not accept them there.
+fn parse_ip(ip: &str) -> Result<IpAddr, String> {
+    if ip.contains('%') {
+        return Err(format!(
+            "{} has a zone identifier, which is not supported in hosts files",
+            ip
+        ));
+    }
+    ip.parse()
+        .map_err(|_| format!("{} is not a valid IP address", ip))
+}
 
-            // The rest are hostnames and possibly a comment
-            let mut hostnames = Vec::new();
-            let mut comment = None;
+/// Check that a hostname is valid according to RFC 1123: at most 253
+/// characters of dot separated labels of 1 to 63 letters, digits and hyphens,
+/// not starting or ending with a hyphen. A single trailing dot is allowed.
+pub fn validate_hostname(hostname: &str) -> Result<(), String> {
+    let name = hostname.strip_suffix('.').unwrap_or(hostname);
+    if name.is_empty() || name.len() > 253 {
+        return Err(format!("{} is not a valid hostname: bad length", hostname));
+    }
+    for label in name.split('.') {
+        let valid = !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid {
+            return Err(format!(
+                "{} is not a valid hostname: bad label \"{}\"",
+                hostname, label
+            ));
+        }
+    }
+    Ok(())
+}
 
-            for part in &parts[1..] {
-                if part.starts_with('#') {
-                    // Join the rest as a comment
-                    let comment_idx = trimmed.find('#').unwrap();
-                    comment = Some(trimmed[comment_idx..].trim().to_string());
-                    break;
-                }
-                hostnames.push(part.to_string());
-            }
+/// What `HostsFile::upsert_entry` changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpsertOutcome {
+    /// The hostname was added to the managed block
+    Added,
+    /// The managed entry pointed at `old_ip`
+    Updated { old_ip: IpAddr },
+    /// An entry not owned by this tool pointed at `old_ip` and was moved into the block
+    TakenOver { old_ip: IpAddr },
+    /// The managed entry already pointed at the IP
+    Unchanged,
+}
 
-            if !hostnames.is_empty() {
-                entries.push(HostEntry {
-                    ip,
-                    hostnames,
-                    comment,
-                });
+impl fmt::Display for UpsertOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UpsertOutcome::Added => write!(f, "added"),
+            UpsertOutcome::Updated { old_ip } => write!(f, "updated (was {})", old_ip),
+            UpsertOutcome::TakenOver { old_ip } => {
+                write!(f, "taken over from an unmanaged entry (was {})", old_ip)
             }
+            UpsertOutcome::Unchanged => write!(f, "already up to date"),
         }
-
-        Ok(entries)
     }
+}
 
-    /// Add a new entry to the hosts file
-    pub fn add_entry(
-        &mut self,
-        ip: &str,
-        hostnames: &[&str],
-        comment: Option<&str>,
-    ) -> io::Result<()> {
-        let entry = HostEntry {
-            ip: ip.to_string(),
-            hostnames: hostnames.iter().map(|&h| h.to_string()).collect(),
-            comment: comment.map(|c| c.to_string()),
-        };
+/// One line of the hosts file. The original text and line ending are kept so
+/// lines that are not edited are written back byte for byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HostLine {
+    text: String,
+    ending: String,
+    entry: Option<HostEntry>,
+}
 
-        // Check if the entry already exists (by hostname)
-        for hostname in &entry.hostnames {
-            if self.contains_hostname(hostname) {
-                return Err(io::Error::new(
-                    io::ErrorKind::AlreadyExists,
-                    format!("Hostname {} already exists in hosts file", hostname),
-                ));
-            }
+impl HostLine {
+    fn from_entry(entry: HostEntry, ending: &str) -> Self {
+        HostLine {
+            text: entry.to_line(),
+            ending: ending.to_string(),
+            entry: Some(entry),
         }
-
-        self.entries.push(entry);
-        self.save()
     }
+}
 
-    /// Check if the hosts file contains a specific hostname
-    pub fn contains_hostname(&self, hostname: &str) -> bool {
-        self.entries
-            .iter()
-            .any(|entry| entry.hostnames.iter().any(|h| h == hostname))
+/// Replaces a file the current process is not allowed to write
+pub trait Elevator {
+    /// Replace `target` with the content of `source`
+    fn replace_file(&self, source: &Path, target: &str) -> io::Result<()>;
+}
+
+/// Elevates through sudo/pkexec (or another command such as doas) on Unix
+/// and a UAC prompt on Windows
+#[derive(Default)]
+pub struct SystemElevator {
+    command: Option<String>,
+}
+
+impl SystemElevator {
+    /// Elevate with `command` instead of sudo/pkexec on Unix
+    pub fn with_command(command: String) -> Self {
+        SystemElevator {
+            command: Some(command),
+        }
     }
+}
 
-    /// Save the current entries back to the hosts file
-    pub fn save(&self) -> io::Result<()> {
-        // Check if we have permission to write to the hosts file
-        // let can_write = Path::new(&self.path)
-        //     .metadata()
-        //     .map(|m| !m.permissions().readonly())
-        //     .unwrap_or(false);
-
-        // if can_write {
-        //     println!("Save host file");
-        //     self.save_direct()
-        // } else {
-        println!("Insufficient permissions to write to hosts file. Attempting to elevate...");
+impl Elevator for SystemElevator {
+    fn replace_file(&self, source: &Path, target: &str) -> io::Result<()> {
         if cfg!(windows) {
-            self.save_with_windows_elevation()
+            Self::replace_with_windows_elevation(source, target)
         } else if cfg!(target_os = "macos") || cfg!(unix) {
-            self.save_with_unix_elevation()
+            self.replace_with_unix_elevation(source, target)
         } else {
             Err(io::Error::new(
                 io::ErrorKind::PermissionDenied,
@@ -141,38 +171,23 @@ impl HostsFile {
             ))
         }
     }
+}
 
-    // fn save_direct(&self) -> io::Result<()> {
-    //     let mut file = OpenOptions::new()
-    //         .write(true)
-    //         .truncate(true)
-    //         .open(&self.path)?;
-
-    //     self.write_hosts_content(&mut file)
-    // }
-
-    fn save_with_windows_elevation(&self) -> io::Result<()> {
-        // Create a temporary file with our hosts content
-        let temp_path = std::env::temp_dir().join("thumed_hosts_temp.txt");
-        let mut temp_file = File::create(&temp_path)?;
-
-        // Write content to temp file
-        self.write_hosts_content(&mut temp_file)?;
-
-        // Use PowerShell to copy the file with elevation
+impl SystemElevator {
+    fn replace_with_windows_elevation(source: &Path, target: &str) -> io::Result<()> {
+        // Use PowerShell to copy the file next to the hosts file and move it
+        // over the original with elevation
         let status = std::process::Command::new("powershell")
             .args([
                 "-Command",
                 &format!(
-                    "Start-Process powershell -Verb RunAs -ArgumentList '-Command Copy-Item -Path \"{}\" -Destination \"{}\" -Force'",
-                    temp_path.display(),
-                    self.path
+                    "Start-Process powershell -Verb RunAs -Wait -ArgumentList '-Command Copy-Item -Path \"{0}\" -Destination \"{1}.thumed-new\" -Force; Move-Item -Path \"{1}.thumed-new\" -Destination \"{1}\" -Force'",
+                    source.display(),
+                    target
                 ),
             ])
             .status()?;
 
-        std::fs::remove_file(temp_path)?;
-
         if status.success() {
             Ok(())
         } else {
@@ -183,16 +198,11 @@ impl HostsFile {
         }
     }
 
-    fn save_with_unix_elevation(&self) -> io::Result<()> {
-        // Create a temporary file with our hosts content
-        let temp_path = std::env::temp_dir().join("thumed_hosts_temp.txt");
-        let mut temp_file = File::create(&temp_path)?;
-
-        // Write content to temp file
-        self.write_hosts_content(&mut temp_file)?;
-
+    fn replace_with_unix_elevation(&self, source: &Path, target: &str) -> io::Result<()> {
         // Use sudo/pkexec to copy the file with elevation
-        let sudo_cmd = if std::process::Command::new("which")
+        let sudo_cmd = if let Some(command) = &self.command {
+            command.as_str()
+        } else if std::process::Command::new("which")
             .arg("pkexec")
             .status()
             .map(|s| s.success())
@@ -203,13 +213,16 @@ impl HostsFile {
             "sudo"
         };
 
+        // Stage a copy of the hosts file keeping its mode and owner, fill it with
+        // the new content and rename it over the original so the swap is atomic.
+        // A bind-mounted hosts file (e.g. in containers) cannot be renamed over,
+        // so fall back to writing in place. The SELinux label is restored when
+        // restorecon is available.
+        let script = r#"cp -p "$1" "$1.thumed-new" && cat "$0" > "$1.thumed-new" && { mv -f "$1.thumed-new" "$1" 2>/dev/null || { cat "$0" > "$1" && rm -f "$1.thumed-new"; }; } && { ! command -v restorecon >/dev/null 2>&1 || restorecon "$1"; }"#;
         let status = std::process::Command::new(sudo_cmd)
-            .args(["cp", temp_path.to_str().unwrap(), &self.path])
+            .args(["sh", "-c", script, &source.to_string_lossy(), target])
             .status()?;
 
-        // Clean up temp file
-        std::fs::remove_file(temp_path)?;
-
         if status.success() {
             Ok(())
         } else {
@@ -219,31 +232,638 @@ impl HostsFile {
             ))
         }
     }
+}
+
+pub struct HostsFile {
+    path: String,
+    lines: Vec<HostLine>,
+    // Content as read, to detect concurrent changes before writing
+    original: String,
+    // Hostnames deliberately taken over from lines not owned by this tool
+    taken_over: Vec<String>,
+    backup_dir: PathBuf,
+    elevator: Box<dyn Elevator>,
+}
+
+impl HostsFile {
+    /// Create a new HostsFile instance by loading the hosts file, from
+    /// HOSTS_FILE_ENV when set and the system's hosts file otherwise
+    pub fn new() -> io::Result<Self> {
+        let mut hosts = Self::open(&Self::get_hosts_file_path())?;
+        if let Ok(command) = std::env::var(constants::ELEVATOR_ENV) {
+            if !command.is_empty() {
+                hosts.set_elevator(Box::new(SystemElevator::with_command(command)));
+            }
+        }
+        Ok(hosts)
+    }
 
-    fn write_hosts_content(&self, writer: &mut impl Write) -> io::Result<()> {
-        // Write the standard localhost entries
-        writeln!(writer, "127.0.0.1       localhost")?;
+    /// Load the hosts file at `path`
+    pub fn open(path: &str) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let mut hosts = Self::parse(path.to_string(), &content);
+        hosts.backup_dir = backups_dir()?;
+        Ok(hosts)
+    }
+
+    /// Use another helper to write the file when it is not writable directly
+    pub fn set_elevator(&mut self, elevator: Box<dyn Elevator>) {
+        self.elevator = elevator;
+    }
+
+    /// Keep the backups of this file in `dir` instead of backups/hosts
+    #[cfg(test)]
+    pub fn set_backup_dir(&mut self, dir: PathBuf) {
+        self.backup_dir = dir;
+    }
+
+    /// Get the path to the hosts file based on the operating system
+    fn get_hosts_file_path() -> String {
+        if let Ok(path) = std::env::var(constants::HOSTS_FILE_ENV) {
+            if !path.is_empty() {
+                return path;
+            }
+        }
         if cfg!(windows) {
-            writeln!(writer, "::1             localhost")?;
+            r"C:\Windows\System32\drivers\etc\hosts".to_string()
         } else {
-            writeln!(
-                writer,
-                "::1             localhost ip6-localhost ip6-loopback"
-            )?;
-        }
-        writeln!(writer)?;
-
-        // Write all custom entries
-        for entry in &self.entries {
-            let mut line = format!("{}    {}", entry.ip, entry.hostnames.join(" "));
-            if let Some(comment) = &entry.comment {
-                line = format!("{}  # {}", line, comment);
+            // Fallback to Unix-like systems
+            "/etc/hosts".to_string()
+        }
+    }
+
+    /// Parse hosts file content, keeping every line as it is
+    fn parse(path: String, content: &str) -> Self {
+        let lines = content
+            .split_inclusive('\n')
+            .map(|line| {
+                let text = line.trim_end_matches('\n').trim_end_matches('\r');
+                HostLine {
+                    text: text.to_string(),
+                    ending: line[text.len()..].to_string(),
+                    entry: HostEntry::parse(text).ok().flatten(),
+                }
+            })
+            .collect();
+
+        Self {
+            path,
+            lines,
+            original: content.to_string(),
+            taken_over: Vec::new(),
+            backup_dir: PathBuf::from("backups").join("hosts"),
+            elevator: Box::new(SystemElevator::default()),
+        }
+    }
+
+    /// Line ending used for new lines: the one of the first line, or the
+    /// platform's for an empty file
+    fn line_ending(&self) -> String {
+        match self.lines.iter().find(|line| !line.ending.is_empty()) {
+            Some(line) => line.ending.clone(),
+            None if cfg!(windows) => "\r\n".to_string(),
+            None => "\n".to_string(),
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &HostEntry> {
+        self.lines.iter().filter_map(|line| line.entry.as_ref())
+    }
+
+    /// Lines that could not be parsed as entries or hold invalid hostnames,
+    /// with their line number and the reason. Unparsable lines are ignored for
+    /// lookups, and every line is written back unchanged.
+    pub fn parse_issues(&self) -> Vec<(usize, String)> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                // Names that resolvers accept anyway (e.g. with underscores)
+                // are still found by lookups, but reported here
+                let issue = match HostEntry::parse(&line.text) {
+                    Ok(Some(entry)) => entry
+                        .hostnames
+                        .iter()
+                        .find_map(|hostname| validate_hostname(hostname).err()),
+                    Ok(None) => None,
+                    Err(e) => Some(e),
+                };
+                issue.map(|e| (i + 1, e))
+            })
+            .collect()
+    }
+
+    /// Point a hostname at `ip`, adding it to the managed block or updating
+    /// the entry this tool owns. Entries owned by someone else are refused
+    /// unless `force` is set, in which case they are moved into the block.
+    /// The file is only written when something changed.
+    pub fn upsert_entry(&mut self, ip: IpAddr, hostname: &str, force: bool) -> io::Result<UpsertOutcome> {
+        let outcome = self.upsert_managed_entry(ip, hostname, force)?;
+        if outcome != UpsertOutcome::Unchanged {
+            self.save()?;
+        }
+        Ok(outcome)
+    }
+
+    /// Upsert a hostname in memory
+    fn upsert_managed_entry(&mut self, ip: IpAddr, hostname: &str, force: bool) -> io::Result<UpsertOutcome> {
+        validate_hostname(hostname).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let new_entry = || HostEntry {
+            ip,
+            hostnames: vec![hostname.to_string()],
+            comment: None,
+        };
+
+        let mut entries = self.managed_entries();
+        if let Some(i) = entries.iter().position(|e| e.hostnames.iter().any(|h| h == hostname)) {
+            let old_ip = entries[i].ip;
+            if old_ip == ip {
+                return Ok(UpsertOutcome::Unchanged);
+            }
+            // Split the hostname off entries that carry several
+            if entries[i].hostnames.len() == 1 {
+                entries[i].ip = ip;
+            } else {
+                entries[i].hostnames.retain(|h| h != hostname);
+                entries.insert(i + 1, new_entry());
+            }
+            self.set_managed_entries(entries);
+            return Ok(UpsertOutcome::Updated { old_ip });
+        }
+
+        let Some(old_ip) = self
+            .entries()
+            .find(|e| e.hostnames.iter().any(|h| h == hostname))
+            .map(|e| e.ip)
+        else {
+            self.add_managed_entry(new_entry())?;
+            return Ok(UpsertOutcome::Added);
+        };
+        if !force {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "Hostname {} already exists in hosts file ({}) and is not managed by this tool",
+                    hostname, old_ip
+                ),
+            ));
+        }
+
+        self.release_unmanaged_hostname(hostname);
+        self.taken_over.push(hostname.to_string());
+        self.add_managed_entry(new_entry())?;
+        Ok(UpsertOutcome::TakenOver { old_ip })
+    }
+
+    /// Drop a hostname from the lines outside the managed block, rewriting only
+    /// the lines that carried it and removing those left without hostnames
+    fn release_unmanaged_hostname(&mut self, hostname: &str) {
+        let range = self.block_range();
+        for (i, line) in self.lines.iter_mut().enumerate() {
+            if range.is_some_and(|(begin, end)| i >= begin && i <= end) {
+                continue;
+            }
+            if let Some(entry) = &mut line.entry {
+                if entry.hostnames.iter().any(|h| h == hostname) {
+                    entry.hostnames.retain(|h| h != hostname);
+                    line.text = entry.to_line();
+                }
             }
-            writeln!(writer, "{}", line)?;
         }
+        self.lines
+            .retain(|line| line.entry.as_ref().is_none_or(|e| !e.hostnames.is_empty()));
+    }
 
+    /// Add an entry to the managed block in memory
+    fn add_managed_entry(&mut self, entry: HostEntry) -> io::Result<()> {
+        // Check if the entry already exists (by hostname)
+        for hostname in &entry.hostnames {
+            if self.contains_hostname(hostname) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("Hostname {} already exists in hosts file", hostname),
+                ));
+            }
+        }
+
+        let mut entries = self.managed_entries();
+        entries.push(entry);
+        self.set_managed_entries(entries);
         Ok(())
     }
+
+    /// Check if the hosts file contains a specific hostname
+    pub fn contains_hostname(&self, hostname: &str) -> bool {
+        self.entries()
+            .any(|entry| entry.hostnames.iter().any(|h| h == hostname))
+    }
+
+    /// Remove hostnames from the managed block, dropping entries left without any.
+    /// Returns the number of hostnames removed; the file is only written when
+    /// something changed.
+    pub fn remove_hostnames(&mut self, hostnames: &[&str]) -> io::Result<usize> {
+        let removed = self.drop_hostnames(hostnames);
+        if removed > 0 {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    /// Remove hostnames from the managed block in memory
+    fn drop_hostnames(&mut self, hostnames: &[&str]) -> usize {
+        let mut removed = 0;
+        let mut entries = self.managed_entries();
+        for entry in &mut entries {
+            let before = entry.hostnames.len();
+            entry.hostnames.retain(|h| !hostnames.contains(&h.as_str()));
+            removed += before - entry.hostnames.len();
+        }
+        if removed > 0 {
+            entries.retain(|entry| !entry.hostnames.is_empty());
+            self.set_managed_entries(entries);
+        }
+        removed
+    }
+
+    /// Make the managed block map exactly `hostnames` to `ip`, in one write.
+    /// Hostnames already present outside the block are left alone. Returns the
+    /// hostnames added and removed.
+    pub fn sync_hostnames(
+        &mut self,
+        ip: IpAddr,
+        hostnames: &[String],
+    ) -> io::Result<(Vec<String>, Vec<String>)> {
+        for hostname in hostnames {
+            validate_hostname(hostname)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        }
+        let before = self.managed_entries();
+        let (added, removed) = self.reconcile_hostnames(ip, hostnames);
+        if self.managed_entries() != before {
+            self.save()?;
+        }
+        Ok((added, removed))
+    }
+
+    /// Rewrite the managed block in memory for `sync_hostnames`
+    fn reconcile_hostnames(&mut self, ip: IpAddr, hostnames: &[String]) -> (Vec<String>, Vec<String>) {
+        let owned = self.owned_hostnames();
+        let desired: Vec<String> = hostnames
+            .iter()
+            .filter(|h| owned.contains(h) || !self.contains_hostname(h))
+            .cloned()
+            .collect();
+        let added = desired
+            .iter()
+            .filter(|h| !owned.contains(h))
+            .cloned()
+            .collect();
+        let removed = owned
+            .into_iter()
+            .filter(|h| !desired.contains(h))
+            .collect();
+        self.set_managed_entries(
+            desired
+                .into_iter()
+                .map(|hostname| HostEntry {
+                    ip,
+                    hostnames: vec![hostname],
+                    comment: None,
+                })
+                .collect(),
+        );
+        (added, removed)
+    }
+
+    /// Remove the whole managed block (and any legacy entries of this tool),
+    /// e.g. before uninstalling the tool
+    pub fn remove_managed_block(&mut self) -> io::Result<usize> {
+        let removed: usize = self
+            .managed_entries()
+            .iter()
+            .map(|entry| entry.hostnames.len())
+            .sum();
+        let had_block = self.block_range().is_some();
+        self.set_managed_entries(Vec::new());
+        if removed > 0 || had_block {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    /// Hostnames of the entries added by this tool
+    pub fn owned_hostnames(&self) -> Vec<String> {
+        self.managed_entries()
+            .into_iter()
+            .flat_map(|entry| entry.hostnames)
+            .collect()
+    }
+
+    /// Line indices of the BEGIN and END markers of the managed block. A block
+    /// without END runs to the end of the file.
+    fn block_range(&self) -> Option<(usize, usize)> {
+        let begin = self
+            .lines
+            .iter()
+            .position(|line| line.text.trim() == constants::HOSTS_BLOCK_BEGIN)?;
+        let end = self.lines[begin..]
+            .iter()
+            .position(|line| line.text.trim() == constants::HOSTS_BLOCK_END)
+            .map(|offset| begin + offset)
+            .unwrap_or(self.lines.len());
+        Some((begin, end))
+    }
+
+    /// Whether a line outside the block was added by an older version of this
+    /// tool, which tagged its entries with a trailing comment
+    fn is_legacy_entry(line: &HostLine) -> bool {
+        line.entry.as_ref().is_some_and(|entry| {
+            entry
+                .comment
+                .as_deref()
+                .is_some_and(|c| c.contains(constants::HOSTS_ENTRY_COMMENT))
+        })
+    }
+
+    /// Entries owned by this tool: those inside the managed block, followed by
+    /// legacy tagged entries elsewhere in the file
+    pub fn managed_entries(&self) -> Vec<HostEntry> {
+        let mut entries = Vec::new();
+        let range = self.block_range();
+        if let Some((begin, end)) = range {
+            entries.extend(self.lines[begin + 1..end].iter().filter_map(|l| l.entry.clone()));
+        }
+        for (i, line) in self.lines.iter().enumerate() {
+            let in_block = range.is_some_and(|(begin, end)| i > begin && i < end);
+            if !in_block && Self::is_legacy_entry(line) {
+                let mut entry = line.entry.clone().unwrap();
+                entry.comment = None;
+                entries.push(entry);
+            }
+        }
+        entries
+    }
+
+    /// Replace the content of the managed block, creating it at the end of the
+    /// file when missing and removing it when `entries` is empty. Legacy tagged
+    /// entries are moved into the block; every other line is left untouched.
+    fn set_managed_entries(&mut self, entries: Vec<HostEntry>) {
+        let ending = self.line_ending();
+        let range = self.block_range();
+        let mut kept = Vec::with_capacity(self.lines.len());
+        let mut block_position = None;
+        for (i, line) in self.lines.drain(..).enumerate() {
+            if range.is_some_and(|(begin, _)| i == begin) {
+                block_position = Some(kept.len());
+            }
+            let in_block = range.is_some_and(|(begin, end)| i >= begin && i <= end);
+            if !in_block && !Self::is_legacy_entry(&line) {
+                kept.push(line);
+            }
+        }
+        self.lines = kept;
+        if entries.is_empty() {
+            return;
+        }
+
+        let mut block = vec![HostLine {
+            text: constants::HOSTS_BLOCK_BEGIN.to_string(),
+            ending: ending.clone(),
+            entry: None,
+        }];
+        block.extend(entries.into_iter().map(|entry| HostLine::from_entry(entry, &ending)));
+        block.push(HostLine {
+            text: constants::HOSTS_BLOCK_END.to_string(),
+            ending: ending.clone(),
+            entry: None,
+        });
+
+        // Keep the block where it was, otherwise append it
+        let position = match block_position {
+            Some(position) => position,
+            None => {
+                if let Some(last) = self.lines.last_mut() {
+                    if last.ending.is_empty() {
+                        last.ending = ending;
+                    }
+                }
+                self.lines.len()
+            }
+        };
+        self.lines.splice(position..position, block);
+    }
+
+    /// Save the current entries back to the hosts file. The new content is
+    /// validated first, the current file is backed up, and the backup is put
+    /// back if the write does not land as expected.
+    pub fn save(&self) -> io::Result<()> {
+        let content = self.render();
+        self.validate(&content)?;
+        self.replace_with_backup(&content)
+    }
+
+    /// Replace the hosts file with the content of a backup
+    pub fn restore_backup(&self, backup: &Path) -> io::Result<()> {
+        let content = fs::read_to_string(backup)?;
+        if Self::parse(self.path.clone(), &content).entries().next().is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Backup {} contains no host entries", backup.display()),
+            ));
+        }
+        self.replace_with_backup(&content)
+    }
+
+    /// Lines that are not owned by this tool
+    fn unmanaged_lines(&self) -> Vec<&str> {
+        let range = self.block_range();
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(i, line)| {
+                !range.is_some_and(|(begin, end)| *i >= begin && *i <= end)
+                    && !Self::is_legacy_entry(line)
+            })
+            .map(|(_, line)| line.text.as_str())
+            .collect()
+    }
+
+    /// Check new content before it replaces the hosts file: the file must not
+    /// have changed since it was read, lines not owned by this tool must be
+    /// unchanged apart from the hostnames taken over from them, and there must
+    /// be at most one well-formed managed block
+    fn validate(&self, content: &str) -> io::Result<()> {
+        let invalid = |message: &str| Err(io::Error::new(io::ErrorKind::InvalidData, message));
+
+        if fs::read_to_string(&self.path)? != self.original {
+            return invalid("The hosts file was modified since it was read, please retry");
+        }
+
+        let new = Self::parse(self.path.clone(), content);
+        let mut original = Self::parse(self.path.clone(), &self.original);
+        for hostname in &self.taken_over {
+            original.release_unmanaged_hostname(hostname);
+        }
+        if new.unmanaged_lines() != original.unmanaged_lines() {
+            return invalid("Refusing to write hosts file: lines not owned by this tool would change");
+        }
+
+        let count = |marker: &str| new.lines.iter().filter(|l| l.text.trim() == marker).count();
+        let (begins, ends) = (
+            count(constants::HOSTS_BLOCK_BEGIN),
+            count(constants::HOSTS_BLOCK_END),
+        );
+        if begins > 1 || begins != ends {
+            return invalid("Refusing to write hosts file: malformed managed block");
+        }
+        Ok(())
+    }
+
+    /// Back up the current file, write the new content and roll back to the
+    /// backup when the file does not end up with exactly that content
+    fn replace_with_backup(&self, content: &str) -> io::Result<()> {
+        let backup = backup_hosts_file(&self.path, &self.backup_dir)?;
+        println!("Hosts file backed up to {}", backup.display());
+
+        let result = self.write_content(content);
+        let written = fs::read_to_string(&self.path)?;
+        if result.is_ok() && written == content {
+            return Ok(());
+        }
+
+        let original = fs::read_to_string(&backup)?;
+        if written != original {
+            eprintln!("The hosts file was not written correctly, restoring the backup...");
+            self.write_content(&original)?;
+        }
+        result?;
+        Err(io::Error::other(
+            "The hosts file was not written correctly and has been restored from the backup",
+        ))
+    }
+
+    /// Replace the hosts file with `content`, directly when the process may
+    /// write it and through the elevator otherwise
+    fn write_content(&self, content: &str) -> io::Result<()> {
+        // Create a temporary file with our hosts content
+        let temp_path = write_temp_file(content)?;
+
+        let result = if self.can_write_directly() {
+            self.replace_directly(&temp_path)
+        } else {
+            println!("Insufficient permissions to write to hosts file. Attempting to elevate...");
+            self.elevator.replace_file(&temp_path, &self.path)
+        };
+
+        // Clean up temp file
+        fs::remove_file(temp_path)?;
+        result
+    }
+
+    /// Whether the file and its directory are writable by this process
+    fn can_write_directly(&self) -> bool {
+        let path = Path::new(&self.path);
+        let writable_file = path
+            .metadata()
+            .map(|m| !m.permissions().readonly())
+            .unwrap_or(false);
+        writable_file
+            && fs::OpenOptions::new().append(true).open(path).is_ok()
+            && path.parent().is_some_and(|dir| {
+                let probe = dir.join(format!(".thumed_probe_{}", std::process::id()));
+                let created = File::create(&probe).is_ok();
+                let _ = fs::remove_file(&probe);
+                created
+            })
+    }
+
+    /// Copy next to the hosts file and rename over it so the swap is atomic.
+    /// A bind-mounted hosts file (e.g. in containers) cannot be renamed over,
+    /// so fall back to writing in place.
+    fn replace_directly(&self, source: &Path) -> io::Result<()> {
+        let staged = format!("{}.thumed-new", self.path);
+        fs::copy(source, &staged)?;
+        fs::set_permissions(&staged, fs::metadata(&self.path)?.permissions())?;
+        if fs::rename(&staged, &self.path).is_err() {
+            fs::remove_file(&staged)?;
+            // Truncating the existing file keeps its mode, owner and label
+            fs::write(&self.path, fs::read(source)?)?;
+        }
+        restore_security_context(&self.path);
+        Ok(())
+    }
+
+    /// The hosts file content: untouched lines verbatim, edited ones re-formatted
+    fn render(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| format!("{}{}", line.text, line.ending))
+            .collect()
+    }
+}
+
+/// Give a replaced file the SELinux label of its path back, when restorecon
+/// is available
+fn restore_security_context(path: &str) {
+    if cfg!(target_os = "linux") {
+        let _ = std::process::Command::new("restorecon")
+            .arg(path)
+            .stderr(std::process::Stdio::null())
+            .status();
+    }
+}
+
+/// Directory holding the hosts file backups
+pub fn backups_dir() -> io::Result<PathBuf> {
+    Ok(std::env::current_dir()?.join("backups").join("hosts"))
+}
+
+/// Write content to a temporary file to be copied over the hosts file
+fn write_temp_file(content: &str) -> io::Result<PathBuf> {
+    let temp_path = std::env::temp_dir().join(format!("thumed_hosts_{}.tmp", std::process::id()));
+    let mut temp_file = File::create(&temp_path)?;
+    temp_file.write_all(content.as_bytes())?;
+    Ok(temp_path)
+}
+
+/// Hosts file backups in backups/hosts, newest first
+pub fn list_backups() -> io::Result<Vec<PathBuf>> {
+    list_backups_in(&backups_dir()?)
+}
+
+fn list_backups_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("hosts-"))
+        })
+        .collect();
+    backups.sort();
+    backups.reverse();
+    Ok(backups)
+}
+
+/// Copy the hosts file to a timestamped backup, keeping the newest
+/// HOSTS_BACKUP_RETENTION backups
+fn backup_hosts_file(path: &str, dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let timestamp = utils::unix_timestamp();
+    let mut backup = dir.join(format!("hosts-{}", timestamp));
+    let mut n = 1;
+    while backup.exists() {
+        backup = dir.join(format!("hosts-{}-{}", timestamp, n));
+        n += 1;
+    }
+    fs::copy(path, &backup)?;
+
+    for old in list_backups_in(dir)?.iter().skip(constants::HOSTS_BACKUP_RETENTION) {
+        fs::remove_file(old)?;
+    }
+    Ok(backup)
 }
 
 #[cfg(test)]
@@ -253,4 +873,251 @@ mod test {
     fn test_list_host() {
         assert!(HostsFile::new().is_ok())
     }
+
+    const SAMPLE: &str = "# Static table lookup for hostnames.\r\n\
+        127.0.0.1\tlocalhost\r\n\
+        \r\n\
+        \t::1   localhost ip6-localhost   # loopback\r\n\
+        10.0.0.1 nas.lan\r\n\
+        # trailing comment without newline";
+
+    #[test]
+    fn test_roundtrip_is_lossless() {
+        let hosts = HostsFile::parse("hosts".to_string(), SAMPLE);
+        assert_eq!(hosts.render(), SAMPLE);
+        assert!(hosts.contains_hostname("ip6-localhost"));
+        assert!(hosts.contains_hostname("nas.lan"));
+    }
+
+    fn demo_entry(hostname: &str) -> HostEntry {
+        HostEntry {
+            ip: "166.111.153.65".parse().unwrap(),
+            hostnames: vec![hostname.to_string()],
+            comment: None,
+        }
+    }
+
+    #[test]
+    fn test_managed_block() {
+        let mut hosts = HostsFile::parse("hosts".to_string(), SAMPLE);
+        hosts.add_managed_entry(demo_entry("demo.apps.med.thu")).unwrap();
+        hosts.add_managed_entry(demo_entry("lab.apps.med.thu")).unwrap();
+        let expected = format!(
+            "{}\r\n# BEGIN thumed_login\r\n\
+            166.111.153.65    demo.apps.med.thu\r\n\
+            166.111.153.65    lab.apps.med.thu\r\n\
+            # END thumed_login\r\n",
+            SAMPLE
+        );
+        assert_eq!(hosts.render(), expected);
+        assert!(hosts.add_managed_entry(demo_entry("nas.lan")).is_err());
+
+        // Removing only rewrites the block, an empty block is dropped
+        assert_eq!(hosts.drop_hostnames(&["demo.apps.med.thu", "nas.lan"]), 1);
+        assert!(hosts.contains_hostname("nas.lan"));
+        assert_eq!(hosts.drop_hostnames(&["lab.apps.med.thu"]), 1);
+        assert_eq!(hosts.render(), format!("{}\r\n", SAMPLE));
+    }
+
+    #[test]
+    fn test_legacy_entries_move_into_block() {
+        let content = "127.0.0.1 localhost\n\
+            166.111.153.65    old.apps.med.thu  # Added by thumed_login\n\
+            # BEGIN thumed_login\n\
+            166.111.153.65    demo.apps.med.thu\n\
+            # END thumed_login\n\
+            10.0.0.1 nas.lan\n";
+        let mut hosts = HostsFile::parse("hosts".to_string(), content);
+        assert_eq!(
+            hosts.owned_hostnames(),
+            vec!["demo.apps.med.thu", "old.apps.med.thu"]
+        );
+
+        hosts.add_managed_entry(demo_entry("new.apps.med.thu")).unwrap();
+        assert_eq!(
+            hosts.render(),
+            "127.0.0.1 localhost\n\
+            # BEGIN thumed_login\n\
+            166.111.153.65    demo.apps.med.thu\n\
+            166.111.153.65    old.apps.med.thu\n\
+            166.111.153.65    new.apps.med.thu\n\
+            # END thumed_login\n\
+            10.0.0.1 nas.lan\n"
+        );
+
+        hosts.set_managed_entries(Vec::new());
+        assert_eq!(hosts.render(), "127.0.0.1 localhost\n10.0.0.1 nas.lan\n");
+    }
+
+    #[test]
+    fn test_reconcile_hostnames() {
+        let mut hosts = HostsFile::parse("hosts".to_string(), SAMPLE);
+        hosts.add_managed_entry(demo_entry("old.apps.med.thu")).unwrap();
+        hosts.add_managed_entry(demo_entry("demo.apps.med.thu")).unwrap();
+
+        let wanted = vec![
+            "demo.apps.med.thu".to_string(),
+            "lab.apps.med.thu".to_string(),
+            "nas.lan".to_string(),
+        ];
+        let (added, removed) = hosts.reconcile_hostnames("166.111.153.65".parse().unwrap(), &wanted);
+        assert_eq!(added, vec!["lab.apps.med.thu"]);
+        assert_eq!(removed, vec!["old.apps.med.thu"]);
+        assert_eq!(
+            hosts.owned_hostnames(),
+            vec!["demo.apps.med.thu", "lab.apps.med.thu"]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let path =
+            std::env::temp_dir().join(format!("thumed_hosts_validate_{}", std::process::id()));
+        fs::write(&path, SAMPLE).unwrap();
+        let mut hosts = HostsFile::parse(path.to_string_lossy().to_string(), SAMPLE);
+        hosts.add_managed_entry(demo_entry("demo.apps.med.thu")).unwrap();
+        assert!(hosts.validate(&hosts.render()).is_ok());
+
+        // Changing a line the tool does not own is refused
+        let tampered = hosts.render().replace("nas.lan", "nas.example");
+        assert!(hosts.validate(&tampered).is_err());
+
+        // So is writing over a file changed by someone else in the meantime
+        fs::write(&path, format!("{}\n10.0.0.2 other.lan\n", SAMPLE)).unwrap();
+        assert!(hosts.validate(&hosts.render()).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// Records the files it is asked to replace instead of elevating
+    struct RecordingElevator(std::rc::Rc<std::cell::RefCell<Vec<String>>>);
+
+    impl Elevator for RecordingElevator {
+        fn replace_file(&self, source: &Path, target: &str) -> io::Result<()> {
+            self.0.borrow_mut().push(target.to_string());
+            fs::copy(source, target).map(|_| ())
+        }
+    }
+
+    #[test]
+    fn test_save_to_custom_path() {
+        let dir = std::env::temp_dir().join(format!("thumed_hosts_save_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hosts");
+        fs::write(&path, SAMPLE).unwrap();
+        let path = path.to_string_lossy().to_string();
+        let calls = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+
+        // Writable file: written directly, with a backup of the old content
+        let mut hosts = HostsFile::open(&path).unwrap();
+        hosts.set_backup_dir(dir.join("backups"));
+        hosts.set_elevator(Box::new(RecordingElevator(calls.clone())));
+        hosts.upsert_entry("166.111.153.65".parse().unwrap(), "demo.apps.med.thu", false).unwrap();
+        assert!(calls.borrow().is_empty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), hosts.render());
+        let backups = list_backups_in(&dir.join("backups")).unwrap();
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), SAMPLE);
+
+        // Read-only file: handed to the elevator
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).unwrap();
+        let mut hosts = HostsFile::open(&path).unwrap();
+        hosts.set_backup_dir(dir.join("backups"));
+        hosts.set_elevator(Box::new(RecordingElevator(calls.clone())));
+        assert_eq!(hosts.remove_hostnames(&["demo.apps.med.thu"]).unwrap(), 1);
+        assert_eq!(*calls.borrow(), vec![path.clone()]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\r\n", SAMPLE));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_upsert() {
+        let dir = std::env::temp_dir().join(format!("thumed_hosts_upsert_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hosts");
+        fs::write(&path, SAMPLE).unwrap();
+        let path = path.to_string_lossy().to_string();
+        let upsert = |ip: &str, hostname: &str, force: bool| {
+            let mut hosts = HostsFile::open(&path).unwrap();
+            hosts.set_backup_dir(dir.join("backups"));
+            hosts.upsert_entry(ip.parse().unwrap(), hostname, force)
+        };
+        assert_eq!(
+            upsert("166.111.153.65", "demo.apps.med.thu", false).unwrap(),
+            UpsertOutcome::Added
+        );
+        assert_eq!(
+            upsert("166.111.153.65", "demo.apps.med.thu", false).unwrap(),
+            UpsertOutcome::Unchanged
+        );
+        assert_eq!(
+            upsert("166.111.153.66", "demo.apps.med.thu", false).unwrap(),
+            UpsertOutcome::Updated {
+                old_ip: "166.111.153.65".parse().unwrap()
+            }
+        );
+
+        // Entries owned by someone else need force
+        let err = upsert("166.111.153.66", "nas.lan", false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(
+            upsert("166.111.153.66", "ip6-localhost", true).unwrap(),
+            UpsertOutcome::TakenOver {
+                old_ip: "::1".parse().unwrap()
+            }
+        );
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# Static table lookup for hostnames.\r\n\
+            127.0.0.1\tlocalhost\r\n\
+            \r\n\
+            ::1    localhost  # loopback\r\n\
+            10.0.0.1 nas.lan\r\n\
+            # trailing comment without newline\r\n\
+            # BEGIN thumed_login\r\n\
+            166.111.153.66    demo.apps.med.thu\r\n\
+            166.111.153.66    ip6-localhost\r\n\
+            # END thumed_login\r\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_issues() {
+        let content = "127.0.0.1 localhost\n\
+            fe80::1%eth0 router\n\
+            2001:db8::1 v6.lan v6\n\
+            300.1.1.1 broken\n\
+            10.0.0.2\n\
+            10.0.0.3 bad_name.lan\n\
+            10.0.0.4 -dash.lan\n";
+        let mut hosts = HostsFile::parse("hosts".to_string(), content);
+        assert_eq!(
+            hosts
+                .parse_issues()
+                .iter()
+                .map(|(line, _)| *line)
+                .collect::<Vec<_>>(),
+            vec![2, 4, 5, 6, 7]
+        );
+        assert!(hosts.contains_hostname("v6.lan"));
+        assert!(!hosts.contains_hostname("router"));
+        // Names failing validation are reported but still found
+        assert!(hosts.contains_hostname("bad_name.lan"));
+
+        // Unparsable lines are kept as they are
+        hosts.add_managed_entry(demo_entry("demo.apps.med.thu")).unwrap();
+        assert!(hosts.render().starts_with(content));
+
+        assert!(validate_hostname("demo.apps.med.thu.").is_ok());
+        assert!(validate_hostname("a..b").is_err());
+        assert!(validate_hostname(&"a".repeat(64)).is_err());
+        let err = hosts
+            .upsert_managed_entry("10.0.0.5".parse().unwrap(), "no spaces", false)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
 }
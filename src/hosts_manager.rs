@@ -0,0 +1,219 @@
+use crate::constants;
+use crate::host_handler::{self, HostsFile};
+use crate::interaction;
+use crate::pod_handler;
+use crate::tools;
+use crate::utils;
+use serde_json::Value;
+use std::error::Error;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+// Hostname of a release's website
+pub fn release_hostname(release: &str) -> String {
+    format!("{}.{}", release, constants::WEBSITE_DOMAIN)
+}
+
+// Whether a name is the website domain or one of its subdomains, with or
+// without the trailing dot of a fully qualified name
+pub fn is_website_hostname(name: &str) -> bool {
+    let name = name.trim_end_matches('.');
+    name == constants::WEBSITE_DOMAIN
+        || name
+            .strip_suffix(constants::WEBSITE_DOMAIN)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+// List the hosts file entries owned by this tool
+pub fn list() -> Result<(), Box<dyn Error>> {
+    let host_file = HostsFile::new()?;
+    for (line, issue) in host_file.parse_issues() {
+        eprintln!("Warning: hosts file line {}: {}", line, issue);
+    }
+    let entries = host_file.managed_entries();
+    if entries.is_empty() {
+        println!("No hosts entries added by this tool.");
+        return Ok(());
+    }
+    println!("Hosts entries added by this tool:");
+    for entry in entries {
+        for hostname in &entry.hostnames {
+            println!("  {:<40} {}", hostname, entry.ip);
+        }
+    }
+    Ok(())
+}
+
+// Add or update the website hostname of a pod. Entries not added by this
+// tool are only replaced with `force`.
+pub fn add(pod_name: &str, ip: Option<IpAddr>, force: bool) -> Result<(), Box<dyn Error>> {
+    let hostname = release_hostname(pod_handler::release_name(pod_name));
+    let ip = ip.unwrap_or(constants::SERVER_IP);
+    let mut host_file = HostsFile::new()?;
+    let outcome = host_file.upsert_entry(ip, &hostname, force)?;
+    println!("Hostname {} -> {}: {}", hostname, ip, outcome);
+    Ok(())
+}
+
+// Remove the website hostname of a pod
+pub fn remove(pod_name: &str) -> Result<(), Box<dyn Error>> {
+    let hostname = release_hostname(pod_handler::release_name(pod_name));
+    let mut host_file = HostsFile::new()?;
+    if host_file.remove_hostnames(&[&hostname])? == 0 {
+        return Err(format!("{} is not managed by this tool", hostname).into());
+    }
+    println!("Hostname {} removed from hosts file.", hostname);
+    Ok(())
+}
+
+// Ingress hostnames of the given releases
+fn ingress_hostnames(releases: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+    let stdout = utils::run_cmd(&tools::kubectl(), &["get", "ingress", "-o", "json"])?;
+    let ingresses: Value = serde_json::from_str(&stdout)?;
+    let mut hostnames = Vec::new();
+    for ingress in ingresses["items"].as_array().into_iter().flatten() {
+        let metadata = &ingress["metadata"];
+        let release = metadata["labels"]["app.kubernetes.io/instance"]
+            .as_str()
+            .or(metadata["annotations"]["meta.helm.sh/release-name"].as_str());
+        if !release.is_some_and(|r| releases.iter().any(|name| name == r)) {
+            continue;
+        }
+        for rule in ingress
+            .pointer("/spec/rules")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(host) = rule["host"].as_str() {
+                hostnames.push(host.to_string());
+            }
+        }
+    }
+    Ok(hostnames)
+}
+
+// Hostnames of the given releases: their release hostnames and the hosts of
+// their ingress rules. The flag is false when the ingress rules could not be
+// read and only the release hostnames are known.
+pub fn release_hostnames(releases: &[String]) -> (Vec<String>, bool) {
+    let mut hostnames: Vec<String> = releases.iter().map(|r| release_hostname(r)).collect();
+    let complete = match ingress_hostnames(releases) {
+        Ok(ingress) => {
+            hostnames.extend(ingress);
+            true
+        }
+        Err(e) => {
+            eprintln!(
+                "Could not read ingress hostnames, using release names only: {}",
+                e
+            );
+            false
+        }
+    };
+    hostnames.sort();
+    hostnames.dedup();
+    (hostnames, complete)
+}
+
+// Reconcile the hosts file with the current releases and their ingress
+// hostnames in a single write
+pub fn sync() -> Result<(), Box<dyn Error>> {
+    let releases = pod_handler::list_releases()?;
+    let (mut hostnames, _) = release_hostnames(&releases);
+    hostnames.retain(|hostname| match host_handler::validate_hostname(hostname) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Skipping {}", e);
+            false
+        }
+    });
+
+    let mut host_file = HostsFile::new()?;
+    let (added, removed) = host_file.sync_hostnames(constants::SERVER_IP, &hostnames)?;
+    for hostname in &added {
+        println!("Added {}", hostname);
+    }
+    for hostname in &removed {
+        println!("Removed {}", hostname);
+    }
+    if added.is_empty() && removed.is_empty() {
+        println!("Hosts file is up to date.");
+    }
+    Ok(())
+}
+
+// Remove the whole block of hosts file entries owned by this tool
+pub fn remove_block(assume_yes: bool) -> Result<(), Box<dyn Error>> {
+    let mut host_file = HostsFile::new()?;
+    let hostnames = host_file.owned_hostnames();
+    if !hostnames.is_empty() {
+        println!("Hosts entries added by this tool:");
+        for hostname in &hostnames {
+            println!("  {}", hostname);
+        }
+    }
+    if !assume_yes && !interaction::confirm("Remove all of them from the hosts file?")? {
+        println!("Nothing removed.");
+        return Ok(());
+    }
+    let removed = host_file.remove_managed_block()?;
+    println!("Removed {} hosts entries.", removed);
+    Ok(())
+}
+
+// Restore the hosts file from a backup, given by number, file name or path;
+// lists the backups and asks when none is given
+pub fn restore(backup: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let backups = host_handler::list_backups()?;
+    let choice = match backup {
+        Some(choice) => choice.to_string(),
+        None => {
+            if backups.is_empty() {
+                return Err("No hosts file backups found".into());
+            }
+            println!("Hosts file backups (newest first):");
+            for (i, path) in backups.iter().enumerate() {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let age = name
+                    .trim_start_matches("hosts-")
+                    .split('-')
+                    .next()
+                    .and_then(|ts| ts.parse::<u64>().ok())
+                    .map(|ts| {
+                        format!(
+                            "{} ago",
+                            utils::format_duration(utils::unix_timestamp().saturating_sub(ts))
+                        )
+                    })
+                    .unwrap_or_default();
+                println!("{}. {} {}", i + 1, name, age);
+            }
+            print!("Backup to restore: ");
+            io::stdout().flush()?;
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            input.trim().to_string()
+        }
+    };
+
+    let path = match choice.parse::<usize>() {
+        Ok(n) if n >= 1 && n <= backups.len() => backups[n - 1].clone(),
+        _ => {
+            let path = PathBuf::from(&choice);
+            if path.exists() {
+                path
+            } else {
+                host_handler::backups_dir()?.join(&choice)
+            }
+        }
+    };
+    if !path.exists() {
+        return Err(format!("Backup {} not found", choice).into());
+    }
+
+    HostsFile::new()?.restore_backup(&path)?;
+    println!("Hosts file restored from {}", path.display());
+    Ok(())
+}
@@ -23,3 +23,14 @@ pub fn get_user_action() -> Result<u8, Error> {
         )),
     }
 }
+
+
+// Ask a yes/no question, defaulting to "no"
+pub fn confirm(prompt: &str) -> Result<bool, Error> {
+    print!("{} [y/N]: ", prompt);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
@@ -0,0 +1,371 @@
+use crate::download::{Downloader, HttpError};
+use crate::environment::UserInfo;
+use crate::{constants, platform, tools, utils};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use serde_json::Value;
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Shown when kubectl has no kubeconfig to read
+pub const MISSING_HINT: &str = "No kubeconfig found. Fetch yours with \
+`kubeconfig fetch --url <endpoint>` or import a file with `kubeconfig import <path>`.";
+
+// Path of the kubeconfig managed by this tool, in its config directory
+pub fn path() -> Result<PathBuf, Box<dyn Error>> {
+    Ok(env::current_dir()?.join("config").join("kubeconfig"))
+}
+
+// Point KUBECONFIG at the stored kubeconfig when there is one, so kubectl,
+// helm and every other child process use it
+pub fn apply_env() {
+    if let Ok(path) = path() {
+        if path.is_file() {
+            unsafe {
+                env::set_var("KUBECONFIG", &path);
+            }
+        }
+    }
+}
+
+// Whether kubectl has a kubeconfig to read, from KUBECONFIG or ~/.kube/config
+pub fn is_configured() -> bool {
+    if let Some(paths) = env::var_os("KUBECONFIG") {
+        return env::split_paths(&paths).any(|p| p.is_file());
+    }
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .is_some_and(|home| Path::new(&home).join(".kube").join("config").is_file())
+}
+
+/// Check that `content` looks like a kubeconfig, in YAML or JSON
+fn validate(content: &str) -> Result<(), String> {
+    let has_clusters = match serde_json::from_str::<Value>(content) {
+        Ok(config) => config["clusters"].is_array(),
+        Err(_) => content.lines().any(|line| line.starts_with("clusters:")),
+    };
+    if has_clusters {
+        Ok(())
+    } else {
+        Err("not a kubeconfig, no clusters found".to_string())
+    }
+}
+
+/// Write the kubeconfig readable by the current user only, replacing the
+/// stored one in a single step
+fn store(content: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let path = path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let staged = path.with_extension("tmp");
+    let mut file = File::create(&staged)?;
+    // Restrict the permissions before any credential is written
+    platform::set_private(&staged)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&staged, &path)?;
+    Ok(path)
+}
+
+/// Use the newly stored kubeconfig and report what it contains
+fn activate(path: &Path) {
+    apply_env();
+    println!("Kubeconfig saved to {}", path.display());
+    if let Err(e) = show() {
+        eprintln!("Could not read back the kubeconfig: {}", e);
+    }
+}
+
+// Store a copy of a kubeconfig file as the one used by this tool
+pub fn import(source: &Path) -> Result<(), Box<dyn Error>> {
+    let content = fs::read_to_string(source).map_err(|e| {
+        eprintln!("Failed to read {}: {}", source.display(), e);
+        e
+    })?;
+    validate(&content).map_err(|e| format!("{}: {}", source.display(), e))?;
+    activate(&store(&content)?);
+    Ok(())
+}
+
+/// Check that credentials can be sent to `url`: only over HTTPS, unless
+/// plain HTTP is explicitly allowed
+fn check_endpoint(url: &str, allow_http: bool) -> Result<(), String> {
+    if url.starts_with("https://") || (allow_http && url.starts_with("http://")) {
+        Ok(())
+    } else if url.starts_with("http://") {
+        Err(format!(
+            "Refusing to send your credentials unencrypted to {}, use an https:// endpoint or pass --allow-http",
+            url
+        ))
+    } else {
+        Err(format!("Unsupported kubeconfig endpoint {}", url))
+    }
+}
+
+// Download the kubeconfig of the user in config/user.config from `url`, or
+// from the endpoint in THUMED_KUBECONFIG_URL
+pub fn fetch(url: Option<&str>, allow_http: bool) -> Result<(), Box<dyn Error>> {
+    let url = match url {
+        Some(url) => url.to_string(),
+        None => match env::var(constants::KUBECONFIG_URL_ENV) {
+            Ok(url) if !url.is_empty() => url,
+            _ => {
+                eprintln!(
+                    "No kubeconfig endpoint configured: pass --url or set {}",
+                    constants::KUBECONFIG_URL_ENV
+                );
+                return Err("No kubeconfig endpoint".into());
+            }
+        },
+    };
+    check_endpoint(&url, allow_http)?;
+    let user_info = UserInfo::load()?;
+    println!("Fetching the kubeconfig of {} from {}", user_info.user, url);
+    let content = Downloader::default()
+        .fetch_text_as(&url, &user_info.user, &user_info.password)
+        .map_err(|e| {
+            eprintln!("Failed to fetch the kubeconfig from {}: {}", url, e);
+            if e.downcast_ref::<HttpError>().is_some_and(|e| e.code == 401) {
+                eprintln!("Check your credentials with `update-user`.");
+            }
+            e
+        })?;
+    validate(&content).map_err(|e| format!("{}: {}", url, e))?;
+    activate(&store(&content)?);
+    Ok(())
+}
+
+/// What `show` reports about the current context
+#[derive(Debug, PartialEq, Eq)]
+struct Summary {
+    context: String,
+    cluster: String,
+    server: String,
+    user: String,
+    /// Unix timestamp at which the credentials expire, when known
+    expiry: Option<u64>,
+}
+
+/// Summarize the output of `kubectl config view --minify --raw -o json`,
+/// which only holds the current context with its cluster and user
+fn summarize(config: &Value) -> Summary {
+    let text = |value: &Value| value.as_str().unwrap_or("<none>").to_string();
+    let context = &config["contexts"][0];
+    let credentials = &config["users"][0]["user"];
+
+    let expiry = if let Some(token) = credentials["token"].as_str() {
+        token_expiry(token)
+    } else if let Some(data) = credentials["client-certificate-data"].as_str() {
+        STANDARD
+            .decode(data.trim())
+            .ok()
+            .and_then(|pem| certificate_expiry(&pem))
+    } else if let Some(file) = credentials["client-certificate"].as_str() {
+        fs::read(file).ok().and_then(|pem| certificate_expiry(&pem))
+    } else {
+        None
+    };
+
+    Summary {
+        context: text(&config["current-context"]),
+        cluster: text(&context["context"]["cluster"]),
+        server: text(&config["clusters"][0]["cluster"]["server"]),
+        user: text(&context["context"]["user"]),
+        expiry,
+    }
+}
+
+/// Expiry of a JSON Web Token from its `exp` claim
+fn token_expiry(token: &str) -> Option<u64> {
+    let payload = token.split('.').nth(1)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    serde_json::from_slice::<Value>(&payload).ok()?["exp"].as_u64()
+}
+
+/// Expiry of an X.509 certificate in PEM or DER form
+fn certificate_expiry(data: &[u8]) -> Option<u64> {
+    let der = match std::str::from_utf8(data) {
+        Ok(pem) if pem.contains("-----BEGIN") => {
+            let body: String = pem
+                .lines()
+                .skip_while(|line| !line.starts_with("-----BEGIN"))
+                .skip(1)
+                .take_while(|line| !line.starts_with("-----END"))
+                .collect();
+            STANDARD.decode(body.trim()).ok()?
+        }
+        _ => data.to_vec(),
+    };
+
+    let (_, certificate, _) = der_element(&der)?;
+    let (_, tbs, _) = der_element(certificate)?;
+    // Skip the optional version, then the serial number, signature algorithm
+    // and issuer to reach the validity
+    let (tag, _, mut rest) = der_element(tbs)?;
+    let skip = if tag == 0xa0 { 3 } else { 2 };
+    for _ in 0..skip {
+        rest = der_element(rest)?.2;
+    }
+    let (_, validity, _) = der_element(rest)?;
+    let (_, _, validity) = der_element(validity)?;
+    let (tag, not_after, _) = der_element(validity)?;
+    asn1_time(tag, std::str::from_utf8(not_after).ok()?)
+}
+
+/// Split the DER element at the start of `data` into its tag, its content
+/// and the data after it
+fn der_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&len, mut data) = data.split_first()?;
+    let len = if len < 0x80 {
+        len as usize
+    } else {
+        let count = (len & 0x7f) as usize;
+        if count == 0 || count > 4 || data.len() < count {
+            return None;
+        }
+        let (bytes, rest) = data.split_at(count);
+        data = rest;
+        bytes.iter().fold(0, |acc, &b| acc << 8 | b as usize)
+    };
+    if data.len() < len {
+        return None;
+    }
+    let (content, rest) = data.split_at(len);
+    Some((tag, content, rest))
+}
+
+/// Unix timestamp of an ASN.1 UTCTime (tag 0x17) or GeneralizedTime (tag
+/// 0x18) in UTC
+fn asn1_time(tag: u8, time: &str) -> Option<u64> {
+    let time = time.strip_suffix('Z')?;
+    let (year, rest) = match tag {
+        0x17 => {
+            let year: i64 = time.get(..2)?.parse().ok()?;
+            (
+                if year < 50 { 2000 + year } else { 1900 + year },
+                time.get(2..)?,
+            )
+        }
+        0x18 => (time.get(..4)?.parse().ok()?, time.get(4..)?),
+        _ => return None,
+    };
+    if rest.len() != 10 || !rest.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |i: usize| rest[i..i + 2].parse::<i64>().unwrap_or(0);
+    let seconds = days_from_civil(year, field(0), field(2)) * 86400
+        + field(4) * 3600
+        + field(6) * 60
+        + field(8);
+    u64::try_from(seconds).ok()
+}
+
+/// Days between 1970-01-01 and a date of the Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// Print the cluster, user and credential expiry of the current context
+pub fn show() -> Result<(), Box<dyn Error>> {
+    if !is_configured() {
+        eprintln!("{}", MISSING_HINT);
+        return Err("No kubeconfig".into());
+    }
+    let output = utils::run_cmd(
+        &tools::kubectl(),
+        &["config", "view", "--minify", "--raw", "-o", "json"],
+    )?;
+    let summary = summarize(&serde_json::from_str(&output)?);
+
+    if let Some(paths) = env::var_os("KUBECONFIG") {
+        println!("Kubeconfig: {}", paths.to_string_lossy());
+    }
+    println!("Context: {}", summary.context);
+    println!("Cluster: {} ({})", summary.cluster, summary.server);
+    println!("User: {}", summary.user);
+    let now = utils::unix_timestamp();
+    match summary.expiry {
+        Some(expiry) if expiry > now => {
+            println!("Expires: in {}", utils::format_duration(expiry - now))
+        }
+        Some(expiry) => {
+            println!("Expired: {} ago", utils::format_duration(now - expiry));
+            println!("Run `kubeconfig fetch` to get new credentials.");
+        }
+        None => println!("Expires: unknown"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    // DER element with a short length
+    fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut element = vec![tag, content.len() as u8];
+        element.extend_from_slice(content);
+        element
+    }
+
+    #[test]
+    fn test_summarize() {
+        // 2030-01-01T00:00:00Z
+        let expiry = 1893456000;
+        let claims = URL_SAFE_NO_PAD.encode(json!({ "sub": "alice", "exp": expiry }).to_string());
+        let config = json!({
+            "current-context": "med",
+            "contexts": [{ "name": "med", "context": { "cluster": "thu-med", "user": "alice" } }],
+            "clusters": [{ "name": "thu-med", "cluster": { "server": "https://10.0.0.1:6443" } }],
+            "users": [{ "name": "alice", "user": { "token": format!("e30.{}.sig", claims) } }],
+        });
+        assert_eq!(
+            summarize(&config),
+            Summary {
+                context: "med".to_string(),
+                cluster: "thu-med".to_string(),
+                server: "https://10.0.0.1:6443".to_string(),
+                user: "alice".to_string(),
+                expiry: Some(expiry),
+            }
+        );
+
+        // Minimal certificate: version, serial, algorithm, issuer, validity
+        let validity = [der(0x17, b"200101000000Z"), der(0x18, b"20300101000000Z")].concat();
+        let tbs = [
+            der(0xa0, &der(0x02, &[2])),
+            der(0x02, &[1]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            der(0x30, &validity),
+        ]
+        .concat();
+        let certificate = der(0x30, &der(0x30, &tbs));
+        let pem = format!(
+            "-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n",
+            STANDARD.encode(&certificate)
+        );
+        assert_eq!(certificate_expiry(pem.as_bytes()), Some(expiry));
+        assert_eq!(asn1_time(0x17, "700101000000Z"), Some(0));
+        assert_eq!(certificate_expiry(b"garbage"), None);
+
+        assert!(validate("apiVersion: v1\nclusters:\n- cluster: {}\n").is_ok());
+        assert!(validate("<html>Unauthorized</html>").is_err());
+
+        assert!(check_endpoint("https://k8s.med.thu/kubeconfig", false).is_ok());
+        assert!(check_endpoint("http://k8s.med.thu/kubeconfig", false).is_err());
+        assert!(check_endpoint("http://k8s.med.thu/kubeconfig", true).is_ok());
+        assert!(check_endpoint("ftp://k8s.med.thu/kubeconfig", true).is_err());
+    }
+}
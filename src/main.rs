@@ -1,6 +1,27 @@
-use crate::{cli, constants, environment, interaction, platform, pod_handler};
+mod backup;
+mod bundle;
+mod checksum;
+mod cli;
+mod constants;
+mod dns;
+mod download;
+mod environment;
+mod host_handler;
+mod hosts_manager;
+mod interaction;
+mod kubeconfig;
+mod metrics;
+mod pac;
+mod platform;
+mod pod_handler;
+mod quota;
+mod release_handler;
+mod tools;
+mod utils;
+
 use clap::Parser;
 use std::env;
+use std::io::{self, IsTerminal};
 use std::process;
 
 fn main() {
@@ -14,6 +35,12 @@ fn main() {
     }
 
     let cli = cli::Cli::parse();
+    if let Some(hosts_file) = &cli.hosts_file {
+        unsafe {
+            env::set_var(constants::HOSTS_FILE_ENV, hosts_file);
+        }
+    }
+    kubeconfig::apply_env();
 
     // If no command is specified or interactive mode is requested, run interactive mode
     if cli.interactive || cli.command.is_none() {
@@ -23,7 +50,9 @@ fn main() {
 
     // Handle command-line mode
     match cli.command.unwrap() {
-        cli::Commands::CheckEnv => environment::check_env(),
+        cli::Commands::CheckEnv { offline_bundle } => {
+            environment::check_env(offline_bundle.as_deref())
+        }
         
         cli::Commands::ListPods => {
             let mut pod_list = pod_handler::PodList::new();
@@ -36,26 +65,50 @@ fn main() {
             }
         }
         
-        cli::Commands::InstallPod { name, cpu, memory } => {
+        cli::Commands::InstallPod { name, cpu, memory, preset, login_shell, check_nodes, force, ttl } => {
+            let ttl = match ttl.as_deref().map(utils::parse_duration).transpose() {
+                Ok(ttl) => ttl,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(1);
+                }
+            };
+            let (cpu, memory) = match pod_handler::apply_preset(preset.as_deref(), cpu, memory) {
+                Ok(resources) => resources,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(1);
+                }
+            };
             let pod_config = match name {
                 Some(pod_name) => {
-                    pod_handler::PodConfig::new_with_params(pod_name, cpu, memory)
+                    pod_handler::PodConfig::new_with_params(pod_name, cpu, memory, login_shell)
                 },
                 None => pod_handler::PodConfig::new(),
             };
             
+            match pod_config.check_capacity(check_nodes) {
+                Ok(true) => {}
+                Ok(false) if force => println!("Quota exceeded, installing anyway (--force)."),
+                Ok(false) => {
+                    eprintln!("The namespace quota cannot fit this pod. Lower --cpu/--memory or use --force.");
+                    process::exit(1);
+                }
+                Err(e) => eprintln!("Error checking capacity: {}", e),
+            }
+            
             if let Err(e) = pod_config.save_config_yaml() {
                 eprintln!("Error saving pod configuration: {}", e);
                 process::exit(1);
             }
             
-            if let Err(e) = pod_config.install_pod() {
+            if let Err(e) = pod_config.install_pod(ttl) {
                 eprintln!("Error installing pod: {}", e);
                 process::exit(1);
             }
         }
         
-        cli::Commands::LoginPod { name } => {
+        cli::Commands::LoginPod { name, shell, container } => {
             let mut pod_list = pod_handler::PodList::new();
             if let Err(e) = pod_list.get_pod_list() {
                 eprintln!("Error getting pod list: {}", e);
@@ -64,14 +117,18 @@ fn main() {
             
             match name {
                 Some(pod_name) => {
-                    if let Err(e) = pod_list.login_pod_by_name(&pod_name) {
+                    if let Err(e) = pod_list.login_pod_by_name(
+                        &pod_name,
+                        shell.as_deref(),
+                        container.as_deref(),
+                    ) {
                         eprintln!("Error logging into pod: {}", e);
                         process::exit(1);
                     }
                 },
                 None => {
                     pod_list.display();
-                    if let Err(e) = pod_list.login_pod() {
+                    if let Err(e) = pod_list.login_pod(shell.as_deref(), container.as_deref()) {
                         eprintln!("Error logging into pod: {}", e);
                         process::exit(1);
                     }
@@ -79,7 +136,28 @@ fn main() {
             }
         }
         
-        cli::Commands::UninstallPod { name } => {
+        cli::Commands::Exec { pod, personal_dir, container, command } => {
+            let mut pod_list = pod_handler::PodList::new();
+            if let Err(e) = pod_list.get_pod_list() {
+                eprintln!("Error getting pod list: {}", e);
+                process::exit(1);
+            }
+            
+            match pod_list.exec_in_pod(&pod, &command, personal_dir, container.as_deref()) {
+                Ok(code) => process::exit(code),
+                Err(e) => {
+                    eprintln!("Error running command in pod: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        
+        cli::Commands::UninstallPod { name, delete_config, backup, backup_paths, no_backup } => {
+            let backup = match backup {
+                Some(target) => backup::BackupPolicy::Backup(target, backup_paths),
+                None if no_backup || !io::stdin().is_terminal() => backup::BackupPolicy::Skip,
+                None => backup::BackupPolicy::Ask,
+            };
             let mut pod_list = pod_handler::PodList::new();
             if let Err(e) = pod_list.get_pod_list() {
                 eprintln!("Error getting pod list: {}", e);
@@ -88,14 +166,14 @@ fn main() {
             
             match name {
                 Some(pod_name) => {
-                    if let Err(e) = pod_list.uninstall_pod_by_name(&pod_name) {
+                    if let Err(e) = pod_list.uninstall_pod_by_name(&pod_name, delete_config, &backup) {
                         eprintln!("Error uninstalling pod: {}", e);
                         process::exit(1);
                     }
                 },
                 None => {
                     pod_list.display();
-                    if let Err(e) = pod_list.uninstall_pod() {
+                    if let Err(e) = pod_list.uninstall_pod(delete_config, &backup) {
                         eprintln!("Error uninstalling pod: {}", e);
                         process::exit(1);
                     }
@@ -103,6 +181,159 @@ fn main() {
             }
         }
         
+        cli::Commands::Top { name, watch, interval } => {
+            if let Err(e) = metrics::top(name.as_deref(), watch, interval) {
+                eprintln!("Error getting pod usage: {}", e);
+                process::exit(1);
+            }
+        }
+        
+        cli::Commands::History { name } => {
+            let release = pod_handler::release_name(&name);
+            if let Err(e) = release_handler::show_history(release) {
+                eprintln!("Error getting release history: {}", e);
+                process::exit(1);
+            }
+        }
+        
+        cli::Commands::Rollback { name, revision } => {
+            let release = pod_handler::release_name(&name);
+            if let Err(e) = release_handler::rollback(release, revision) {
+                eprintln!("Error rolling back release: {}", e);
+                process::exit(1);
+            }
+        }
+        
+        cli::Commands::Extend { name, by } => {
+            let by = match utils::parse_duration(&by) {
+                Ok(by) => by,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(1);
+                }
+            };
+            if let Err(e) = release_handler::extend(pod_handler::release_name(&name), by) {
+                eprintln!("Error extending pod lifetime: {}", e);
+                process::exit(1);
+            }
+        }
+        
+        cli::Commands::Reap { dry_run, yes } => {
+            if let Err(e) = release_handler::reap(dry_run, yes) {
+                eprintln!("Error reaping expired pods: {}", e);
+                process::exit(1);
+            }
+        }
+        
+        cli::Commands::Hosts { action } => {
+            let result = match action {
+                cli::HostsAction::List => hosts_manager::list(),
+                cli::HostsAction::Add { pod, ip, force } => {
+                    hosts_manager::add(&pod, ip, force)
+                }
+                cli::HostsAction::Remove { pod } => hosts_manager::remove(&pod),
+                cli::HostsAction::Sync => hosts_manager::sync(),
+                cli::HostsAction::Restore { backup } => hosts_manager::restore(backup.as_deref()),
+            };
+            if let Err(e) = result {
+                eprintln!("Error updating hosts file: {}", e);
+                process::exit(1);
+            }
+        }
+        
+        cli::Commands::Dns { action } => match action {
+            cli::DnsAction::Serve { listen, upstream } => {
+                if let Err(e) = dns::serve(listen, upstream) {
+                    eprintln!("Error running DNS stub: {}", e);
+                    process::exit(1);
+                }
+            }
+            cli::DnsAction::Setup { listen } => dns::print_setup(listen),
+        },
+
+        cli::Commands::Pac { action } => {
+            let result = match action {
+                cli::PacAction::Generate { proxy, output } => pac::generate(proxy, output.as_deref()),
+                cli::PacAction::Serve { listen } => pac::serve(listen),
+            };
+            if let Err(e) = result {
+                eprintln!("Error with PAC proxy: {}", e);
+                process::exit(1);
+            }
+        }
+
+        cli::Commands::Tools { action } => {
+            let result = tools::bin_dir().map_err(Into::into).and_then(|bin_dir| match action {
+                cli::ToolsAction::List => tools::list(&bin_dir),
+                cli::ToolsAction::Install { tool, version } => {
+                    let version = version.unwrap_or_else(|| tool.configured_version().to_string());
+                    tools::install(&bin_dir, tool, &version).map(|_| ())
+                }
+                cli::ToolsAction::Upgrade { tool } => match tool {
+                    Some(tool) => tools::upgrade(&bin_dir, tool),
+                    None => tools::ALL_TOOLS
+                        .iter()
+                        .try_for_each(|tool| tools::upgrade(&bin_dir, *tool)),
+                },
+                cli::ToolsAction::Pin { tool, version } => {
+                    tools::pin(&bin_dir, tool, version.as_deref())
+                }
+            });
+            if let Err(e) = result {
+                eprintln!("Error managing tools: {}", e);
+                process::exit(1);
+            }
+        }
+
+        cli::Commands::Bundle { output, targets } => {
+            if let Err(e) = bundle::create(&output, &targets) {
+                eprintln!("Error creating bundle: {}", e);
+                process::exit(1);
+            }
+        }
+
+        cli::Commands::Kubeconfig { action } => {
+            let result = match action {
+                cli::KubeconfigAction::Import { path } => kubeconfig::import(&path),
+                cli::KubeconfigAction::Fetch { url, allow_http } => {
+                    kubeconfig::fetch(url.as_deref(), allow_http)
+                }
+                cli::KubeconfigAction::Show => kubeconfig::show(),
+            };
+            if let Err(e) = result {
+                eprintln!("Error managing kubeconfig: {}", e);
+                process::exit(1);
+            }
+        }
+
+        cli::Commands::Cleanup { yes, hosts_block } => {
+            let result = if hosts_block {
+                hosts_manager::remove_block(yes)
+            } else {
+                pod_handler::cleanup_orphans(yes)
+            };
+            if let Err(e) = result {
+                eprintln!("Error cleaning up: {}", e);
+                process::exit(1);
+            }
+        }
+        
+        cli::Commands::Restore { name, archive, remote } => {
+            let mut pod_list = pod_handler::PodList::new();
+            if let Err(e) = pod_list.get_pod_list() {
+                eprintln!("Error getting pod list: {}", e);
+                process::exit(1);
+            }
+            if !pod_list.pod_list.contains(&name) {
+                eprintln!("Pod {} not found in the list.", name);
+                process::exit(1);
+            }
+            if let Err(e) = backup::restore_pod(&name, &archive, remote) {
+                eprintln!("Error restoring backup: {}", e);
+                process::exit(1);
+            }
+        }
+        
         cli::Commands::UpdateUser => {
             if let Err(e) = environment::UserInfo::update_user() {
                 eprintln!("Error updating user info: {}", e);
@@ -121,7 +352,7 @@ fn run_interactive_mode() {
         match interaction::get_user_action() {
             Ok(action) => match action {
                 0 => break,
-                1 => environment::check_env(),
+                1 => environment::check_env(None),
                 2 => {
                     if let Err(e) = pod_list.get_pod_list() {
                         println!("Error getting pod list: {}", e);
@@ -131,11 +362,19 @@ fn run_interactive_mode() {
                 }
                 3 => {
                     let pod_config = pod_handler::PodConfig::new();
+                    match pod_config.check_capacity(false) {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            println!("The namespace quota cannot fit this pod, please request fewer resources.");
+                            continue;
+                        }
+                        Err(e) => println!("Error checking capacity: {}", e),
+                    }
                     if let Err(e) = pod_config.save_config_yaml() {
                         println!("Error saving pod configuration: {}", e);
                         continue;
                     }
-                    if let Err(e) = pod_config.install_pod() {
+                    if let Err(e) = pod_config.install_pod(None) {
                         println!("Error installing pod: {}", e);
                     }
                 }
@@ -145,7 +384,7 @@ fn run_interactive_mode() {
                         continue;
                     }
                     pod_list.display();
-                    if let Err(e) = pod_list.login_pod() {
+                    if let Err(e) = pod_list.login_pod(None, None) {
                         println!("Error logging into pod: {}", e);
                     }
                 }
@@ -155,7 +394,7 @@ fn run_interactive_mode() {
                         continue;
                     }
                     pod_list.display();
-                    if let Err(e) = pod_list.uninstall_pod() {
+                    if let Err(e) = pod_list.uninstall_pod(false, &backup::BackupPolicy::Ask) {
                         println!("Error uninstalling pod: {}", e);
                     }
                 }
@@ -0,0 +1,264 @@
+use crate::constants;
+use crate::tools;
+use crate::utils;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+// A pod is considered oversized when its peak usage stays below this
+// fraction of its limits
+const UNDERUSE_RATIO: f64 = 0.25;
+// Headroom kept above the peak usage when suggesting a preset
+const PRESET_HEADROOM: f64 = 1.5;
+// Samples needed in watch mode before a preset is suggested; a single sample
+// says nothing about peaks, so nothing is suggested without watch
+const MIN_SAMPLES_FOR_SUGGESTION: u32 = 3;
+
+pub const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+// Parse a Kubernetes CPU quantity ("250m", "2", "1.5", "12345n") into millicores
+pub fn parse_cpu_millis(quantity: &str) -> Option<f64> {
+    let quantity = quantity.trim();
+    let (number, scale) = if let Some(n) = quantity.strip_suffix('n') {
+        (n, 1e-6)
+    } else if let Some(n) = quantity.strip_suffix('u') {
+        (n, 1e-3)
+    } else if let Some(n) = quantity.strip_suffix('m') {
+        (n, 1.0)
+    } else {
+        (quantity, 1000.0)
+    };
+    number.parse::<f64>().ok().map(|n| n * scale)
+}
+
+// Parse a Kubernetes memory quantity ("512Mi", "50Gi", "1G", "1048576") into bytes
+pub fn parse_memory_bytes(quantity: &str) -> Option<f64> {
+    const SUFFIXES: &[(&str, f64)] = &[
+        ("Ki", 1024.0),
+        ("Mi", 1024.0 * 1024.0),
+        ("Gi", GIB),
+        ("Ti", GIB * 1024.0),
+        ("k", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+    ];
+    let quantity = quantity.trim();
+    for (suffix, scale) in SUFFIXES {
+        if let Some(number) = quantity.strip_suffix(suffix) {
+            return number.parse::<f64>().ok().map(|n| n * scale);
+        }
+    }
+    quantity.parse::<f64>().ok()
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Limits {
+    pub cpu_millis: Option<f64>,
+    pub memory_bytes: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Usage {
+    pub cpu_millis: f64,
+    pub memory_bytes: f64,
+}
+
+// Sum the container limits of every pod
+pub fn get_pod_limits() -> Result<HashMap<String, Limits>, Box<dyn Error>> {
+    let stdout = utils::run_cmd(&tools::kubectl(), &["get", "pods", "-o", "json"])?;
+    let pods: Value = serde_json::from_str(&stdout)?;
+    let mut limits = HashMap::new();
+    for pod in pods["items"].as_array().into_iter().flatten() {
+        let Some(name) = pod.pointer("/metadata/name").and_then(Value::as_str) else {
+            continue;
+        };
+        let mut pod_limits = Limits::default();
+        for container in pod
+            .pointer("/spec/containers")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let container_limits = &container["resources"]["limits"];
+            if let Some(cpu) = container_limits["cpu"].as_str().and_then(parse_cpu_millis) {
+                *pod_limits.cpu_millis.get_or_insert(0.0) += cpu;
+            }
+            if let Some(memory) = container_limits["memory"]
+                .as_str()
+                .and_then(parse_memory_bytes)
+            {
+                *pod_limits.memory_bytes.get_or_insert(0.0) += memory;
+            }
+        }
+        limits.insert(name.to_string(), pod_limits);
+    }
+    Ok(limits)
+}
+
+// Current usage of every pod, from `kubectl top pod`
+pub fn get_pod_usage() -> Result<Vec<(String, Usage)>, Box<dyn Error>> {
+    let stdout = utils::run_cmd(&tools::kubectl(), &["top", "pod", "--no-headers"])?;
+    let mut usage = Vec::new();
+    for line in stdout.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 3 {
+            continue;
+        }
+        if let (Some(cpu_millis), Some(memory_bytes)) =
+            (parse_cpu_millis(parts[1]), parse_memory_bytes(parts[2]))
+        {
+            usage.push((
+                parts[0].to_string(),
+                Usage {
+                    cpu_millis,
+                    memory_bytes,
+                },
+            ));
+        }
+    }
+    Ok(usage)
+}
+
+fn percent(used: f64, limit: Option<f64>) -> String {
+    match limit {
+        Some(limit) if limit > 0.0 => format!("{:.0}%", used / limit * 100.0),
+        _ => "-".to_string(),
+    }
+}
+
+fn display(usage: &[(String, Usage)], limits: &HashMap<String, Limits>) {
+    println!(
+        "{:<32}{:>10}{:>10}{:>7}{:>12}{:>12}{:>7}",
+        "POD", "CPU", "LIMIT", "CPU%", "MEMORY", "LIMIT", "MEM%"
+    );
+    for (pod, used) in usage {
+        let limit = limits.get(pod).copied().unwrap_or_default();
+        println!(
+            "{:<32}{:>10}{:>10}{:>7}{:>12}{:>12}{:>7}",
+            pod,
+            format!("{:.2}", used.cpu_millis / 1000.0),
+            limit
+                .cpu_millis
+                .map(|c| format!("{:.0}", c / 1000.0))
+                .unwrap_or("-".to_string()),
+            percent(used.cpu_millis, limit.cpu_millis),
+            format!("{:.1}Gi", used.memory_bytes / GIB),
+            limit
+                .memory_bytes
+                .map(|m| format!("{:.0}Gi", m / GIB))
+                .unwrap_or("-".to_string()),
+            percent(used.memory_bytes, limit.memory_bytes),
+        );
+    }
+}
+
+// Smallest preset that fits the peak usage with some headroom, if it is
+// smaller than the current limits and the pod stayed far below them
+pub fn suggest_preset(peak: &Usage, limit: &Limits) -> Option<&'static (&'static str, u8, u8)> {
+    let (cpu_limit, memory_limit) = (limit.cpu_millis?, limit.memory_bytes?);
+    if peak.cpu_millis > cpu_limit * UNDERUSE_RATIO
+        || peak.memory_bytes > memory_limit * UNDERUSE_RATIO
+    {
+        return None;
+    }
+    constants::RESOURCE_PRESETS.iter().find(|(_, cpu, memory)| {
+        let preset_cpu = *cpu as f64 * 1000.0;
+        let preset_memory = *memory as f64 * GIB;
+        preset_cpu >= peak.cpu_millis * PRESET_HEADROOM
+            && preset_memory >= peak.memory_bytes * PRESET_HEADROOM
+            && (preset_cpu < cpu_limit || preset_memory < memory_limit)
+    })
+}
+
+// Show pod usage against limits, refreshing every `interval` seconds in
+// watch mode and then suggesting a smaller preset for oversized pods
+pub fn top(pod: Option<&str>, watch: bool, interval: u64) -> Result<(), Box<dyn Error>> {
+    let mut peaks: HashMap<String, Usage> = HashMap::new();
+    let mut samples = 0;
+    loop {
+        // Read on every refresh so pods started or resized meanwhile show up
+        let limits = get_pod_limits()?;
+        let usage: Vec<(String, Usage)> = get_pod_usage()?
+            .into_iter()
+            .filter(|(name, _)| {
+                pod.is_none_or(|p| name == p || name.starts_with(&format!("{}-", p)))
+            })
+            .collect();
+        samples += 1;
+        for (name, used) in &usage {
+            let peak = peaks.entry(name.clone()).or_insert(*used);
+            peak.cpu_millis = peak.cpu_millis.max(used.cpu_millis);
+            peak.memory_bytes = peak.memory_bytes.max(used.memory_bytes);
+        }
+
+        if watch {
+            // Clear the screen before redrawing
+            print!("\x1B[2J\x1B[H");
+            println!("Sample {} (every {}s, Ctrl-C to stop)", samples, interval);
+        }
+        display(&usage, &limits);
+
+        if !watch {
+            println!("Run with --watch to sample usage over time and get preset suggestions.");
+            return Ok(());
+        }
+        if samples >= MIN_SAMPLES_FOR_SUGGESTION {
+            for (name, peak) in &peaks {
+                let limit = limits.get(name).copied().unwrap_or_default();
+                if let Some((preset, cpu, memory)) = suggest_preset(peak, &limit) {
+                    println!(
+                        "{} peaked at {:.2} CPU and {:.1}Gi over {} samples; consider the \"{}\" preset ({} CPU, {}GB): InstallPod --preset {}",
+                        name,
+                        peak.cpu_millis / 1000.0,
+                        peak.memory_bytes / GIB,
+                        samples,
+                        preset,
+                        cpu,
+                        memory,
+                        preset
+                    );
+                }
+            }
+        }
+
+        thread::sleep(Duration::from_secs(interval));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_quantities() {
+        assert_eq!(parse_cpu_millis("250m"), Some(250.0));
+        assert_eq!(parse_cpu_millis("2"), Some(2000.0));
+        assert_eq!(parse_cpu_millis("1500000n"), Some(1.5));
+        assert_eq!(parse_memory_bytes("512Mi"), Some(512.0 * 1024.0 * 1024.0));
+        assert_eq!(parse_memory_bytes("1G"), Some(1e9));
+        assert_eq!(parse_memory_bytes("1024"), Some(1024.0));
+        assert_eq!(parse_memory_bytes("abc"), None);
+    }
+
+    #[test]
+    fn test_suggest_preset() {
+        let limit = Limits {
+            cpu_millis: Some(32000.0),
+            memory_bytes: Some(50.0 * GIB),
+        };
+        let idle = Usage {
+            cpu_millis: 1500.0,
+            memory_bytes: 3.0 * GIB,
+        };
+        assert_eq!(suggest_preset(&idle, &limit).map(|p| p.0), Some("small"));
+
+        let busy = Usage {
+            cpu_millis: 20000.0,
+            memory_bytes: 3.0 * GIB,
+        };
+        assert!(suggest_preset(&busy, &limit).is_none());
+    }
+}
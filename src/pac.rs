@@ -0,0 +1,262 @@
+use crate::constants;
+use crate::hosts_manager;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+// Largest request head accepted by the forwarding proxy
+const MAX_HEAD_LEN: usize = 64 * 1024;
+// Path the PAC file is served at
+const PAC_PATH: &str = "/proxy.pac";
+
+// Proxy auto-config script sending the website domain through `proxy` and
+// everything else direct
+pub fn pac_script(proxy: SocketAddr) -> String {
+    format!(
+        r#"// Generated by thumed_login: route *.{domain} through the forwarding proxy
+function FindProxyForURL(url, host) {{
+    if (host == "{domain}" || dnsDomainIs(host, ".{domain}")) {{
+        return "PROXY {proxy}";
+    }}
+    return "DIRECT";
+}}
+"#,
+        domain = constants::WEBSITE_DOMAIN,
+        proxy = proxy
+    )
+}
+
+// Write the PAC file to `output`, or print it when no path is given
+pub fn generate(proxy: SocketAddr, output: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let script = pac_script(proxy);
+    match output {
+        Some(path) => {
+            fs::write(path, script).map_err(|e| {
+                eprintln!("Failed to write {}: {}", path, e);
+                e
+            })?;
+            println!("PAC file written to {}", path);
+            println!(
+                "Start the forwarding proxy with `pac serve --listen {}` before using it.",
+                proxy
+            );
+        }
+        None => print!("{}", script),
+    }
+    Ok(())
+}
+
+/// Where a proxied request goes
+#[derive(Debug, PartialEq, Eq)]
+enum Target {
+    /// Request for the PAC file itself
+    Pac,
+    /// CONNECT tunnel to a port of a website host
+    Tunnel(String, u16),
+    /// Plain HTTP request for a website host
+    Http(String, u16),
+}
+
+/// Work out the target of a request from its request line
+fn parse_target(request_line: &str) -> Option<Target> {
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?;
+    let uri = parts.next()?;
+
+    let (authority, default_port) = if method.eq_ignore_ascii_case("CONNECT") {
+        (uri, 443)
+    } else if let Some(rest) = uri.strip_prefix("http://") {
+        (rest.split('/').next().unwrap_or(rest), 80)
+    } else if uri.split('?').next() == Some(PAC_PATH) {
+        return Some(Target::Pac);
+    } else {
+        return None;
+    };
+    let authority = authority.rsplit('@').next().unwrap_or(authority);
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (authority, default_port),
+    };
+    let host = host.to_ascii_lowercase();
+    if method.eq_ignore_ascii_case("CONNECT") {
+        Some(Target::Tunnel(host, port))
+    } else {
+        Some(Target::Http(host, port))
+    }
+}
+
+/// Read the request head, returning it together with any body bytes read
+/// past it
+fn read_head(client: &mut TcpStream) -> io::Result<(String, Vec<u8>)> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            let rest = data.split_off(end + 4);
+            return Ok((String::from_utf8_lossy(&data).to_string(), rest));
+        }
+        if data.len() > MAX_HEAD_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Request head too large",
+            ));
+        }
+        let len = client.read(&mut buf)?;
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Connection closed before the request head",
+            ));
+        }
+        data.extend_from_slice(&buf[..len]);
+    }
+}
+
+/// Copy data both ways until either side closes
+fn pipe(client: TcpStream, server: TcpStream) -> io::Result<()> {
+    let mut client_reader = client.try_clone()?;
+    let mut server_writer = server.try_clone()?;
+    let upload = thread::spawn(move || {
+        let _ = io::copy(&mut client_reader, &mut server_writer);
+        let _ = server_writer.shutdown(Shutdown::Write);
+    });
+    let (mut server_reader, mut client_writer) = (server, client);
+    let _ = io::copy(&mut server_reader, &mut client_writer);
+    let _ = client_writer.shutdown(Shutdown::Write);
+    let _ = upload.join();
+    Ok(())
+}
+
+/// Send a short response with `status` and `body`
+fn respond(client: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write!(
+        client,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// Handle one client connection of the forwarding proxy
+fn handle_client(mut client: TcpStream, pac: &str) -> io::Result<()> {
+    let (head, rest) = read_head(&mut client)?;
+    let request_line = head.lines().next().unwrap_or_default();
+    let (host, port, tunnel) = match parse_target(request_line) {
+        Some(Target::Pac) => {
+            return respond(
+                &mut client,
+                "200 OK",
+                "application/x-ns-proxy-autoconfig",
+                pac,
+            );
+        }
+        Some(Target::Tunnel(host, port)) => (host, port, true),
+        Some(Target::Http(host, port)) => (host, port, false),
+        None => {
+            return respond(
+                &mut client,
+                "400 Bad Request",
+                "text/plain",
+                "Unsupported request\n",
+            );
+        }
+    };
+    // Only the website domain is forwarded, this is not a general proxy
+    if !hosts_manager::is_website_hostname(&host) {
+        let message = format!(
+            "Only *.{} is served by this proxy\n",
+            constants::WEBSITE_DOMAIN
+        );
+        return respond(&mut client, "403 Forbidden", "text/plain", &message);
+    }
+
+    let mut server = match TcpStream::connect((constants::SERVER_IP, port)) {
+        Ok(server) => server,
+        Err(e) => {
+            let message = format!(
+                "Could not connect to {}:{}: {}\n",
+                constants::SERVER_IP,
+                port,
+                e
+            );
+            return respond(&mut client, "502 Bad Gateway", "text/plain", &message);
+        }
+    };
+    if tunnel {
+        client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")?;
+    } else {
+        // Servers accept absolute request URIs, so the head is passed on as is
+        server.write_all(head.as_bytes())?;
+    }
+    server.write_all(&rest)?;
+    pipe(client, server)
+}
+
+// Serve the PAC file and forward requests for the website domain to the
+// server, whatever the names resolve to locally
+pub fn serve(listen: SocketAddr) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(listen).map_err(|e| {
+        eprintln!("Failed to listen on {}: {}", listen, e);
+        e
+    })?;
+    let pac = pac_script(listen);
+    println!(
+        "Forwarding *.{} to {} through the proxy on {}",
+        constants::WEBSITE_DOMAIN,
+        constants::SERVER_IP,
+        listen
+    );
+    println!(
+        "Set your browser's automatic proxy configuration URL to http://{}{}",
+        listen, PAC_PATH
+    );
+    println!("Press Ctrl+C to stop.");
+
+    for client in listener.incoming() {
+        let client = match client {
+            Ok(client) => client,
+            Err(e) => {
+                eprintln!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let pac = pac.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_client(client, &pac) {
+                eprintln!("Proxy connection failed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(
+            parse_target("CONNECT demo.apps.med.thu:443 HTTP/1.1"),
+            Some(Target::Tunnel("demo.apps.med.thu".to_string(), 443))
+        );
+        assert_eq!(
+            parse_target("GET http://Demo.apps.med.thu/rstudio/?a=1 HTTP/1.1"),
+            Some(Target::Http("demo.apps.med.thu".to_string(), 80))
+        );
+        assert_eq!(
+            parse_target("GET http://demo.apps.med.thu:8080 HTTP/1.1"),
+            Some(Target::Http("demo.apps.med.thu".to_string(), 8080))
+        );
+        assert_eq!(parse_target("GET /proxy.pac HTTP/1.1"), Some(Target::Pac));
+        assert_eq!(parse_target("GET /other HTTP/1.1"), None);
+
+        assert!(hosts_manager::is_website_hostname("demo.apps.med.thu"));
+        assert!(!hosts_manager::is_website_hostname("evilapps.med.thu"));
+        assert!(pac_script("127.0.0.1:7070".parse().unwrap()).contains("PROXY 127.0.0.1:7070"));
+    }
+}
@@ -15,17 +15,43 @@ pub fn get_bin_path(bin_dir: &Path, name: &str) -> PathBuf {
     bin_dir.join(get_exe_name(name))
 }
 
+// Make a file executable (rwxr-xr-x); executables need no mode on Windows
+pub fn set_executable(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+// Make a file readable and writable by its owner only, for credentials (unix)
+pub fn set_private(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
 // Check if we're running on Windows
 pub fn is_windows() -> bool {
     OS == "windows"
 }
 
 // Check if we're running on Unix-like OS (Linux/macOS)
+#[allow(dead_code)]
 pub fn is_unix() -> bool {
     OS == "linux" || OS == "macos"
 }
 
 // Get the appropriate shell command for the platform
+#[allow(dead_code)]
 pub fn get_shell_cmd() -> &'static str {
     if is_windows() {
         "powershell"
@@ -35,6 +61,7 @@ pub fn get_shell_cmd() -> &'static str {
 }
 
 // Get the appropriate path separator for the platform
+#[allow(dead_code)]
 pub fn get_path_separator() -> &'static str {
     if is_windows() {
         ";"
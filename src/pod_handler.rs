@@ -1,19 +1,31 @@
+use crate::backup;
 use crate::constants;
 use crate::environment;
 use crate::host_handler;
+use crate::hosts_manager;
+use crate::interaction;
+use crate::kubeconfig;
+use crate::quota;
+use crate::release_handler;
+use crate::tools;
 use crate::utils;
+use serde_json::Value;
+use std::collections::HashMap;
 use std::env;
 use std::error::Error;
 use std::fs;
 use std::io;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
 use std::process::Command;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct PodConfig {
     container_name: String,
     cpu: Option<u8>,
     memory: Option<u8>,
+    login_shell: Option<String>,
 }
 
 impl PodConfig {
@@ -76,11 +88,17 @@ impl PodConfig {
             container_name,
             cpu,
             memory,
+            login_shell: None,
         }
     }
     
     // Create a new PodConfig with provided parameters
-    pub fn new_with_params(container_name: String, cpu: Option<u8>, memory: Option<u8>) -> Self {
+    pub fn new_with_params(
+        container_name: String,
+        cpu: Option<u8>,
+        memory: Option<u8>,
+        login_shell: Option<String>,
+    ) -> Self {
         // Validate container name
         if !container_name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()) || container_name.is_empty() {
             panic!("Invalid pod name: must contain only lowercase letters and numbers");
@@ -90,6 +108,7 @@ impl PodConfig {
             container_name,
             cpu,
             memory,
+            login_shell,
         }
     }
     fn get_cpu(&self) -> u8 {
@@ -98,18 +117,23 @@ impl PodConfig {
     fn get_memory(&self) -> u8 {
         self.memory.unwrap_or(constants::DEFAULT_MEMORY_GB)
     }
+    // Check the requested resources against the namespace quota (and node
+    // capacity when `check_nodes` is set); false when the quota cannot fit them
+    pub fn check_capacity(&self, check_nodes: bool) -> Result<bool, Box<dyn Error>> {
+        quota::check_capacity(self.get_cpu(), self.get_memory(), check_nodes)
+    }
     pub fn save_config_yaml(&self) -> io::Result<()> {
         let user_info = environment::UserInfo::load().unwrap();
         let yaml_content = format!(
             r#"replicaCount: 1
 
 image:
-  repository: base.med.thu/public/rstudio
+  repository: {image}
   pullPolicy: Always
   tag: "v1"
 
 containerName: "{container_name}"
-
+{login_shell}
 service:
   type: ClusterIP
   port: 8787
@@ -137,7 +161,12 @@ nfs: "Aries"
 
 transfer: false
         "#,
+            image = constants::DEFAULT_IMAGE_REPOSITORY,
             container_name = self.container_name,
+            login_shell = match &self.login_shell {
+                Some(shell) => format!("loginShell: \"{}\"\n", shell),
+                None => String::new(),
+            },
             cpu = self.get_cpu(),
             memory = self.get_memory(),
             username = user_info.user,
@@ -151,14 +180,15 @@ transfer: false
         println!("Configuration saved to {}", file_path.display());
         Ok(())
     }
-    pub fn install_pod(&self) -> Result<(), Box<dyn Error>> {
+    // Install the pod's helm release; `ttl` records an expiry for `Reap`
+    pub fn install_pod(&self, ttl: Option<Duration>) -> Result<(), Box<dyn Error>> {
         let config_dir = env::current_dir()?.join("config");
         let file_path = config_dir.join(format!("{}.yaml", self.container_name));
         if !file_path.exists() {
             eprintln!("Configuration file not found: {}", file_path.display());
             return Ok(());
         }
-        let output = Command::new("helm")
+        let output = Command::new(tools::helm())
             .args([
                 "install",
                 &self.container_name,
@@ -175,23 +205,22 @@ transfer: false
             );
             return Ok(());
         }
+        if let Err(e) = release_handler::mark_managed(&self.container_name, ttl) {
+            eprintln!("Error recording pod annotations: {}", e);
+        }
         match host_handler::HostsFile::new() {
             Ok(mut host_file) => {
-                let hostname = format!("{}.{}", self.container_name, constants::WEBSITE_DOMAIN);
-                match host_file.add_entry(
-                    constants::SERVER_IP,
-                    &[&hostname],
-                    Some("Added by thumed_login"),
-                ) {
-                    Ok(_) => {
-                        println!("Hostname {} added to hosts file.", hostname);
+                let hostname = hosts_manager::release_hostname(&self.container_name);
+                match host_file.upsert_entry(constants::SERVER_IP, &hostname, false) {
+                    Ok(outcome) => {
+                        println!("Hostname {} {}.", hostname, outcome);
                         Ok(())
                     }
                     Err(e) => {
                         eprintln!(
-                            "Error adding hostname to hosts file: {}.\n
-                            You may need to add {} manually.",
-                            e, hostname
+                            "Error adding hostname to hosts file: {}.\n\
+                            Retry with `hosts add {}` once the problem is fixed.",
+                            e, self.container_name
                         );
                         Ok(())
                     }
@@ -199,9 +228,8 @@ transfer: false
             }
             Err(e) => {
                 eprintln!(
-                    "Error creating hosts file: {}. \n
-                    You may need to manually add host:\n{} {}",
-                    constants::SERVER_IP,
+                    "Error reading hosts file: {}.\n\
+                    Retry with `hosts add {}` once the problem is fixed.",
                     e, self.container_name
                 );
                 Ok(())
@@ -210,18 +238,166 @@ transfer: false
     }
 }
 
+// Fill CPU and memory from a resource preset; explicit values take precedence
+pub fn apply_preset(
+    preset: Option<&str>,
+    cpu: Option<u8>,
+    memory: Option<u8>,
+) -> Result<(Option<u8>, Option<u8>), String> {
+    let Some(preset) = preset else {
+        return Ok((cpu, memory));
+    };
+    match constants::RESOURCE_PRESETS.iter().find(|(name, _, _)| *name == preset) {
+        Some((_, preset_cpu, preset_memory)) => {
+            Ok((cpu.or(Some(*preset_cpu)), memory.or(Some(*preset_memory))))
+        }
+        None => {
+            let names: Vec<&str> = constants::RESOURCE_PRESETS.iter().map(|p| p.0).collect();
+            Err(format!("Unknown preset {} (available: {})", preset, names.join(", ")))
+        }
+    }
+}
+
+// Release name of a pod (pods are named `<release>-<hash>`)
+pub fn release_name(pod_name: &str) -> &str {
+    pod_name.split('-').next().unwrap_or(pod_name)
+}
+
+// Path of the values file saved for a release
+pub fn config_file_path(release: &str) -> io::Result<PathBuf> {
+    Ok(env::current_dir()?
+        .join("config")
+        .join(format!("{}.yaml", release)))
+}
+
+// Read a top-level or nested `key: value` from a saved values file
+pub fn read_config_value(release: &str, key: &str) -> Option<String> {
+    let content = fs::read_to_string(config_file_path(release).ok()?).ok()?;
+    let prefix = format!("{}:", key);
+    content.lines().find_map(|line| {
+        let value = line.trim().strip_prefix(&prefix)?.trim();
+        let value = value.trim_matches('"');
+        if value.is_empty() {
+            None
+        } else {
+            Some(value.to_string())
+        }
+    })
+}
+
+// Command used to start a login entrypoint: scripts are run through `sh`,
+// anything else is executed directly
+fn shell_command(entrypoint: &str) -> Vec<String> {
+    if entrypoint.ends_with(".sh") {
+        vec!["sh".to_string(), entrypoint.to_string()]
+    } else {
+        vec![entrypoint.to_string()]
+    }
+}
+
+// Check whether a login entrypoint is available in the pod
+fn probe_login_shell(pod_name: &str, entrypoint: &str, container: Option<&str>) -> bool {
+    let mut args = vec!["exec", pod_name];
+    if let Some(container) = container {
+        args.push("-c");
+        args.push(container);
+    }
+    args.push("--");
+    if entrypoint.ends_with(".sh") {
+        // The path is passed as an argument so the shell never parses it
+        args.extend(["sh", "-c", r#"test -f "$1""#, "sh", entrypoint]);
+    } else {
+        args.extend([entrypoint, "-c", "exit 0"]);
+    }
+    utils::run_cmd(&tools::kubectl(), &args).is_ok()
+}
+
+// Pick the login entrypoint for a pod: the `--shell` flag, then the
+// `loginShell` of the release's values file, then the default of its image,
+// then the first available of LOGIN_SHELL_CANDIDATES
+fn resolve_login_shell(
+    pod_name: &str,
+    shell: Option<&str>,
+    container: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    let release = release_name(pod_name);
+    let image_default = read_config_value(release, "repository").and_then(|image| {
+        constants::IMAGE_LOGIN_SHELLS
+            .iter()
+            .find(|(repository, _)| *repository == image)
+            .map(|(_, entrypoint)| entrypoint.to_string())
+    });
+    let configured = shell
+        .map(|s| s.to_string())
+        .or_else(|| read_config_value(release, "loginShell"))
+        .or(image_default);
+    let has_configured = configured.is_some();
+
+    let mut candidates: Vec<String> = configured.into_iter().collect();
+    for candidate in constants::LOGIN_SHELL_CANDIDATES {
+        if !candidates.iter().any(|c| c == candidate) {
+            candidates.push(candidate.to_string());
+        }
+    }
+
+    for (i, candidate) in candidates.iter().enumerate() {
+        if probe_login_shell(pod_name, candidate, container) {
+            return Ok(candidate.clone());
+        }
+        if i == 0 && has_configured {
+            eprintln!("{} is not available in pod {}, falling back", candidate, pod_name);
+        }
+    }
+    Err(format!("No login shell available in pod {}", pod_name).into())
+}
+
+// Path of the user's personal data directory inside a pod, read from the
+// volume mounts of its containers
+pub fn personal_data_dir(pod_name: &str, user: &str) -> Result<String, Box<dyn Error>> {
+    let stdout = utils::run_cmd(&tools::kubectl(), &["get", "pod", pod_name, "-o", "json"])?;
+    find_personal_mount(&serde_json::from_str(&stdout)?, user).ok_or_else(|| {
+        format!(
+            "No personal data directory of {} is mounted in pod {}",
+            user, pod_name
+        )
+        .into()
+    })
+}
+
+/// Mount path of the personal data directory of `user` in a pod spec. The
+/// chart mounts each loadDataPath.personal entry, named after the user, so
+/// either the mount path or the sub path of the volume ends with it.
+fn find_personal_mount(pod: &Value, user: &str) -> Option<String> {
+    let is_user_dir = |path: &str| path.trim_end_matches('/').rsplit('/').next() == Some(user);
+    pod["spec"]["containers"]
+        .as_array()?
+        .iter()
+        .flat_map(|container| container["volumeMounts"].as_array().into_iter().flatten())
+        .find_map(|mount| {
+            let path = mount["mountPath"].as_str()?;
+            let sub_path = mount["subPath"].as_str().unwrap_or_default();
+            (is_user_dir(path) || is_user_dir(sub_path)).then(|| path.to_string())
+        })
+}
+
 pub struct PodList {
     pub pod_list: Vec<String>,
+    // Remaining lifetime of releases installed with a TTL
+    pub lifetimes: HashMap<String, String>,
 }
 
 impl PodList {
     pub fn new() -> Self {
         PodList {
             pod_list: Vec::new(),
+            lifetimes: HashMap::new(),
         }
     }
     pub fn get_pod_list(&mut self) -> Result<(), Box<dyn Error>> {
-        match utils::run_cmd("kubectl", &["get", "pods"]) {
+        if !kubeconfig::is_configured() {
+            return Err(kubeconfig::MISSING_HINT.into());
+        }
+        match utils::run_cmd(&tools::kubectl(), &["get", "pods"]) {
             Ok(stdout) => {
                 let lines: Vec<&str> = stdout.lines().collect();
                 let mut pod_list = Vec::new();
@@ -232,6 +408,15 @@ impl PodList {
                     }
                 }
                 self.pod_list = pod_list;
+                // Lifetimes are informational, ignore failures to read them
+                self.lifetimes = release_handler::get_release_metadata()
+                    .map(|releases| {
+                        releases
+                            .into_iter()
+                            .filter_map(|(release, meta)| meta.remaining().map(|r| (release, r)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
                 Ok(())
             },
             Err(e) => {
@@ -246,25 +431,51 @@ impl PodList {
         for pod in &self.pod_list {
             let website =
                 "http://".to_string() + pod.split('-').collect::<Vec<&str>>()[0] + "." + constants::WEBSITE_DOMAIN + "/";
-            println!("Pod ID: {}; Website: \"{}\"", pod, website);
+            match self.lifetimes.get(release_name(pod)) {
+                Some(remaining) => println!(
+                    "Pod ID: {}; Website: \"{}\"; Expires: {}",
+                    pod, website, remaining
+                ),
+                None => println!("Pod ID: {}; Website: \"{}\"", pod, website),
+            }
         }
     }
-    pub fn login_pod(&self) -> Result<(), Box<dyn Error>> {
+    // Ask for the pod to log into, then log in like `login_pod_by_name`
+    pub fn login_pod(
+        &self,
+        shell: Option<&str>,
+        container: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
         println!("Please input the pod name you want to login:");
         let mut pod_name = String::new();
         io::stdin().read_line(&mut pod_name)?;
         let pod_name = pod_name.trim();
         
-        self.login_pod_by_name(pod_name)
+        self.login_pod_by_name(pod_name, shell, container)
     }
     
-    // Login to a pod by its name (for CLI usage)
-    pub fn login_pod_by_name(&self, pod_name: &str) -> Result<(), Box<dyn Error>> {
+    // Login to a pod by its name (for CLI usage).
+    // `shell` overrides the configured entrypoint, `container` selects a
+    // container in multi-container pods.
+    pub fn login_pod_by_name(
+        &self,
+        pod_name: &str,
+        shell: Option<&str>,
+        container: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
         if self.pod_list.contains(&pod_name.to_string()) {
-            println!("Connecting to pod: {}...", pod_name);
+            let entrypoint = resolve_login_shell(pod_name, shell, container)?;
+            println!("Connecting to pod: {} ({})...", pod_name, entrypoint);
+            let mut args = vec!["exec".to_string(), "-it".to_string(), pod_name.to_string()];
+            if let Some(container) = container {
+                args.push("-c".to_string());
+                args.push(container.to_string());
+            }
+            args.push("--".to_string());
+            args.extend(shell_command(&entrypoint));
             // Use Command::status to run interactively instead of output
-            match Command::new("kubectl")
-                .args(["exec", "-it", pod_name, "--", "sh", "/cmd.sh"])
+            match Command::new(tools::kubectl())
+                .args(&args)
                 .status() {
                     Ok(status) => {
                         if !status.success() {
@@ -283,42 +494,284 @@ impl PodList {
             Err(format!("Pod {} not found in the list", pod_name).into())
         }
     }
-    pub fn uninstall_pod(&mut self) -> Result<(), Box<dyn Error>> {
+
+    // Run a one-off command in a pod and return the remote exit code.
+    // A TTY is only allocated when stdin is a terminal, so the command can be
+    // scripted and fed through a pipe.
+    pub fn exec_in_pod(
+        &self,
+        pod_name: &str,
+        command: &[String],
+        in_personal_dir: bool,
+        container: Option<&str>,
+    ) -> Result<i32, Box<dyn Error>> {
+        if !self.pod_list.contains(&pod_name.to_string()) {
+            eprintln!("Pod {} not found in the list.", pod_name);
+            return Err(format!("Pod {} not found in the list", pod_name).into());
+        }
+        if command.is_empty() {
+            return Err("No command given".into());
+        }
+
+        let mut args = vec!["exec".to_string(), "-i".to_string()];
+        if io::stdin().is_terminal() {
+            args.push("-t".to_string());
+        }
+        args.push(pod_name.to_string());
+        if let Some(container) = container {
+            args.push("-c".to_string());
+            args.push(container.to_string());
+        }
+        args.push("--".to_string());
+        if in_personal_dir {
+            let user_info = environment::UserInfo::load()?;
+            // `cd` first, then replace the shell with the requested command
+            args.push("sh".to_string());
+            args.push("-c".to_string());
+            args.push(r#"cd "$0" && exec "$@""#.to_string());
+            args.push(personal_data_dir(pod_name, &user_info.user)?);
+        }
+        args.extend(command.iter().cloned());
+
+        match Command::new(tools::kubectl()).args(&args).status() {
+            Ok(status) => Ok(status.code().unwrap_or(1)),
+            Err(e) => {
+                eprintln!("Failed to execute kubectl command: {}", e);
+                Err(e.into())
+            }
+        }
+    }
+    // Ask for the pod to uninstall, then uninstall it like
+    // `uninstall_pod_by_name`
+    pub fn uninstall_pod(
+        &mut self,
+        delete_config: bool,
+        backup: &backup::BackupPolicy,
+    ) -> Result<(), Box<dyn Error>> {
         println!("Please input the pod name you want to uninstall:");
         let mut pod_name = String::new();
         io::stdin().read_line(&mut pod_name)?;
         pod_name = pod_name.trim().to_string();
         
-        self.uninstall_pod_by_name(&pod_name)
+        self.uninstall_pod_by_name(&pod_name, delete_config, backup)
     }
     
-    // Uninstall a pod by its name (for CLI usage)
-    pub fn uninstall_pod_by_name(&mut self, pod_name: &str) -> Result<(), Box<dyn Error>> {
+    // Uninstall a pod by its name (for CLI usage).
+    // Backs up the workspace first according to `backup`, then removes the
+    // pod's hosts entry and archives its values file, or deletes it when
+    // `delete_config` is set.
+    pub fn uninstall_pod_by_name(
+        &mut self,
+        pod_name: &str,
+        delete_config: bool,
+        backup: &backup::BackupPolicy,
+    ) -> Result<(), Box<dyn Error>> {
         if !self.pod_list.contains(&pod_name.to_string()) {
             eprintln!("Pod {} not found in the list.", pod_name);
             return Err(format!("Pod {} not found in the list", pod_name).into());
         }
         
-        let podname_split = pod_name.split('-').next().unwrap_or(pod_name);
-        
-        match Command::new("helm")
-            .args(["uninstall", podname_split])
-            .output() {
-                Ok(output) => {
-                    if output.status.success() {
-                        println!("Pod uninstalled successfully.");
-                        self.get_pod_list()?;
-                        Ok(())
-                    } else {
-                        let error_msg = String::from_utf8_lossy(&output.stderr);
-                        eprintln!("Error uninstalling pod: {}", error_msg);
-                        Err(format!("Failed to uninstall pod: {}", error_msg).into())
-                    }
-                },
-                Err(e) => {
-                    eprintln!("Failed to run helm uninstall command: {}", e);
-                    Err(e.into())
+        if let Err(e) = backup::backup_before_uninstall(pod_name, backup) {
+            eprintln!("Backup failed, pod was not uninstalled: {}", e);
+            return Err(e);
+        }
+        uninstall_release(release_name(pod_name), delete_config)?;
+        self.get_pod_list()?;
+        Ok(())
+    }
+}
+
+// Uninstall a helm release and clean up its hosts entry and values file
+pub fn uninstall_release(release: &str, delete_config: bool) -> Result<(), Box<dyn Error>> {
+    match Command::new(tools::helm())
+        .args(["uninstall", release])
+        .output() {
+            Ok(output) => {
+                if output.status.success() {
+                    println!("Pod {} uninstalled successfully.", release);
+                    remove_release_files(release, delete_config);
+                    Ok(())
+                } else {
+                    let error_msg = String::from_utf8_lossy(&output.stderr);
+                    eprintln!("Error uninstalling pod: {}", error_msg);
+                    Err(format!("Failed to uninstall pod: {}", error_msg).into())
+                }
+            },
+            Err(e) => {
+                eprintln!("Failed to run helm uninstall command: {}", e);
+                Err(e.into())
+            }
+        }
+}
+
+// Names of the helm releases currently installed
+pub fn list_releases() -> Result<Vec<String>, Box<dyn Error>> {
+    let stdout = utils::run_cmd(&tools::helm(), &["list", "-q"])?;
+    Ok(stdout
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+
+// Remove the hosts entry and values file left behind by an uninstalled release.
+// Failures are reported but do not fail the uninstall itself.
+fn remove_release_files(release: &str, delete_config: bool) {
+    let hostname = hosts_manager::release_hostname(release);
+    match host_handler::HostsFile::new() {
+        Ok(mut host_file) => {
+            if host_file.contains_hostname(&hostname) {
+                match host_file.remove_hostnames(&[&hostname]) {
+                    Ok(n) if n > 0 => println!("Hostname {} removed from hosts file.", hostname),
+                    Ok(_) => println!(
+                        "Hostname {} is not managed by this tool, left it in the hosts file.",
+                        hostname
+                    ),
+                    Err(e) => eprintln!(
+                        "Error removing {} from hosts file: {}. You may need to remove it manually.",
+                        hostname, e
+                    ),
                 }
             }
+        }
+        Err(e) => eprintln!("Error reading hosts file: {}", e),
+    }
+
+    let result = if delete_config {
+        delete_config_file(release)
+    } else {
+        archive_config_file(release)
+    };
+    if let Err(e) = result {
+        eprintln!("Error cleaning up values file of {}: {}", release, e);
+    }
+}
+
+fn delete_config_file(release: &str) -> io::Result<()> {
+    let file_path = config_file_path(release)?;
+    if file_path.exists() {
+        fs::remove_file(&file_path)?;
+        println!("Deleted {}", file_path.display());
+    }
+    Ok(())
+}
+
+// Move a values file to config/archive/<release>-<timestamp>.yaml
+fn archive_config_file(release: &str) -> io::Result<()> {
+    let file_path = config_file_path(release)?;
+    if !file_path.exists() {
+        return Ok(());
+    }
+    let archive_dir = env::current_dir()?.join("config").join("archive");
+    fs::create_dir_all(&archive_dir)?;
+    let archive_path =
+        archive_dir.join(format!("{}-{}.yaml", release, utils::unix_timestamp()));
+    fs::rename(&file_path, &archive_path)?;
+    println!(
+        "Archived {} to {} (it contains your registry password, delete it when no longer needed)",
+        file_path.display(),
+        archive_path.display()
+    );
+    Ok(())
+}
+
+// Release names of the values files saved in config/
+fn saved_config_releases() -> io::Result<Vec<String>> {
+    let config_dir = env::current_dir()?.join("config");
+    let mut releases = Vec::new();
+    if !config_dir.exists() {
+        return Ok(releases);
+    }
+    for entry in fs::read_dir(config_dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "yaml") {
+            if let Some(stem) = path.file_stem() {
+                releases.push(stem.to_string_lossy().to_string());
+            }
+        }
+    }
+    releases.sort();
+    Ok(releases)
+}
+
+// Find hosts entries and values files whose release no longer exists and
+// offer to remove them
+pub fn cleanup_orphans(assume_yes: bool) -> Result<(), Box<dyn Error>> {
+    let releases = list_releases()?;
+    let suffix = format!(".{}", constants::WEBSITE_DOMAIN);
+    // The same hostnames `hosts sync` writes, so ingress hosts of live
+    // releases are not mistaken for orphans
+    let (expected, complete) = hosts_manager::release_hostnames(&releases);
+
+    let mut host_file = host_handler::HostsFile::new()?;
+    let orphan_hosts: Vec<String> = host_file
+        .owned_hostnames()
+        .into_iter()
+        .filter(|hostname| {
+            // Without the ingress hosts, only release hostnames can be judged
+            hostname.strip_suffix(&suffix).is_some_and(|name| {
+                !expected.contains(hostname) && (complete || !name.contains('.'))
+            })
+        })
+        .collect();
+    let orphan_configs: Vec<String> = saved_config_releases()?
+        .into_iter()
+        .filter(|release| !releases.contains(release))
+        .collect();
+
+    if orphan_hosts.is_empty() && orphan_configs.is_empty() {
+        println!("Nothing to clean up.");
+        return Ok(());
+    }
+    if !orphan_hosts.is_empty() {
+        println!("Hosts entries without a release:");
+        for hostname in &orphan_hosts {
+            println!("  {}", hostname);
+        }
+    }
+    if !orphan_configs.is_empty() {
+        println!("Values files without a release:");
+        for release in &orphan_configs {
+            println!("  {}", config_file_path(release)?.display());
+        }
+    }
+
+    if !assume_yes && !interaction::confirm("Remove them?")? {
+        println!("Nothing removed.");
+        return Ok(());
+    }
+    if !orphan_hosts.is_empty() {
+        let hostnames: Vec<&str> = orphan_hosts.iter().map(|h| h.as_str()).collect();
+        let removed = host_file.remove_hostnames(&hostnames)?;
+        println!("Removed {} hosts entries.", removed);
+    }
+    for release in &orphan_configs {
+        delete_config_file(release)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_find_personal_mount() {
+        let pod = json!({ "spec": { "containers": [{ "volumeMounts": [
+            { "name": "public", "mountPath": "/mnt/input", "subPath": "input" },
+            { "name": "personal", "mountPath": "/home/rstudio/alice", "subPath": "users/alice" },
+        ] }] } });
+        assert_eq!(
+            find_personal_mount(&pod, "alice"),
+            Some("/home/rstudio/alice".to_string())
+        );
+        assert_eq!(find_personal_mount(&pod, "bob"), None);
+
+        let pod = json!({ "spec": { "containers": [{ "volumeMounts": [
+            { "name": "nfs", "mountPath": "/workspace", "subPath": "personal/alice/" },
+        ] }] } });
+        assert_eq!(find_personal_mount(&pod, "alice"), Some("/workspace".to_string()));
     }
 }
@@ -0,0 +1,174 @@
+use crate::metrics::{parse_cpu_millis, parse_memory_bytes, GIB};
+use crate::tools;
+use crate::utils;
+use serde_json::Value;
+use std::error::Error;
+
+// Quota resources a pod's request counts against. The chart only sets limits,
+// so Kubernetes defaults the requests to the same values.
+const CPU_RESOURCES: &[&str] = &["limits.cpu", "requests.cpu", "cpu"];
+const MEMORY_RESOURCES: &[&str] = &["limits.memory", "requests.memory", "memory"];
+
+#[derive(Debug, PartialEq)]
+pub struct Headroom {
+    pub quota: String,
+    pub resource: String,
+    pub hard: f64,
+    pub used: f64,
+    pub requested: f64,
+}
+
+impl Headroom {
+    pub fn remaining(&self) -> f64 {
+        self.hard - self.used
+    }
+
+    pub fn fits(&self) -> bool {
+        self.requested <= self.remaining()
+    }
+
+    fn format_amount(&self, amount: f64) -> String {
+        if self.resource.ends_with("memory") {
+            format!("{:.1}Gi", amount / GIB)
+        } else {
+            format!("{:.2}", amount / 1000.0)
+        }
+    }
+}
+
+// Compare a request (in millicores and bytes) with every CPU and memory
+// resource of the namespace's ResourceQuotas (`kubectl get resourcequota -o json`)
+pub fn quota_headroom(quotas: &Value, cpu_millis: f64, memory_bytes: f64) -> Vec<Headroom> {
+    let mut headroom = Vec::new();
+    for quota in quotas["items"].as_array().into_iter().flatten() {
+        let name = quota
+            .pointer("/metadata/name")
+            .and_then(Value::as_str)
+            .unwrap_or("-");
+        let hard = &quota["status"]["hard"];
+        let used = &quota["status"]["used"];
+        let resources = CPU_RESOURCES
+            .iter()
+            .map(|r| (r, parse_cpu_millis as fn(&str) -> Option<f64>, cpu_millis))
+            .chain(MEMORY_RESOURCES.iter().map(|r| {
+                (
+                    r,
+                    parse_memory_bytes as fn(&str) -> Option<f64>,
+                    memory_bytes,
+                )
+            }));
+        for (resource, parse, requested) in resources {
+            let Some(hard) = hard[*resource].as_str().and_then(parse) else {
+                continue;
+            };
+            let used = used[*resource].as_str().and_then(parse).unwrap_or(0.0);
+            headroom.push(Headroom {
+                quota: name.to_string(),
+                resource: resource.to_string(),
+                hard,
+                used,
+                requested,
+            });
+        }
+    }
+    headroom
+}
+
+// Largest allocatable CPU (millicores) and memory (bytes) of any node
+fn largest_node_allocatable() -> Result<(f64, f64), Box<dyn Error>> {
+    let stdout = utils::run_cmd(&tools::kubectl(), &["get", "nodes", "-o", "json"])?;
+    let nodes: Value = serde_json::from_str(&stdout)?;
+    let mut largest = (0.0_f64, 0.0_f64);
+    for node in nodes["items"].as_array().into_iter().flatten() {
+        let allocatable = &node["status"]["allocatable"];
+        let cpu = allocatable["cpu"]
+            .as_str()
+            .and_then(parse_cpu_millis)
+            .unwrap_or(0.0);
+        let memory = allocatable["memory"]
+            .as_str()
+            .and_then(parse_memory_bytes)
+            .unwrap_or(0.0);
+        largest = (largest.0.max(cpu), largest.1.max(memory));
+    }
+    Ok(largest)
+}
+
+// Check whether a pod with the given CPU cores and memory (GB) fits the
+// namespace quota and, optionally, the largest node. Prints the remaining
+// headroom and returns false when the quota cannot satisfy the request.
+pub fn check_capacity(cpu: u8, memory: u8, check_nodes: bool) -> Result<bool, Box<dyn Error>> {
+    let cpu_millis = cpu as f64 * 1000.0;
+    let memory_bytes = memory as f64 * GIB;
+    let mut fits = true;
+
+    match utils::run_cmd(&tools::kubectl(), &["get", "resourcequota", "-o", "json"]) {
+        Ok(stdout) => {
+            let quotas: Value = serde_json::from_str(&stdout)?;
+            let headroom = quota_headroom(&quotas, cpu_millis, memory_bytes);
+            if headroom.is_empty() {
+                println!("No resource quota set for this namespace.");
+            }
+            for h in &headroom {
+                println!(
+                    "Quota {} {}: requested {}, remaining {} of {}{}",
+                    h.quota,
+                    h.resource,
+                    h.format_amount(h.requested),
+                    h.format_amount(h.remaining().max(0.0)),
+                    h.format_amount(h.hard),
+                    if h.fits() { "" } else { " (insufficient)" }
+                );
+                fits &= h.fits();
+            }
+        }
+        Err(e) => println!("Could not read the namespace quota, skipping check: {}", e),
+    }
+
+    if check_nodes {
+        match largest_node_allocatable() {
+            Ok((node_cpu, node_memory)) => {
+                println!(
+                    "Largest node allocatable: {:.2} CPU, {:.1}Gi",
+                    node_cpu / 1000.0,
+                    node_memory / GIB
+                );
+                if cpu_millis > node_cpu || memory_bytes > node_memory {
+                    println!(
+                        "Warning: no node can fit {} CPU and {}GB, the pod would stay Pending.",
+                        cpu, memory
+                    );
+                }
+            }
+            Err(e) => println!("Could not read node capacity, skipping check: {}", e),
+        }
+    }
+
+    Ok(fits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quota_headroom() {
+        let quotas: Value = serde_json::from_str(
+            r#"{"items": [{
+                "metadata": {"name": "compute"},
+                "status": {
+                    "hard": {"limits.cpu": "64", "limits.memory": "100Gi", "pods": "10"},
+                    "used": {"limits.cpu": "40", "limits.memory": "20Gi"}
+                }
+            }]}"#,
+        )
+        .unwrap();
+        let headroom = quota_headroom(&quotas, 32000.0, 50.0 * GIB);
+        assert_eq!(headroom.len(), 2);
+        assert_eq!(headroom[0].resource, "limits.cpu");
+        assert_eq!(headroom[0].remaining(), 24000.0);
+        assert!(!headroom[0].fits());
+        assert_eq!(headroom[1].resource, "limits.memory");
+        assert!(headroom[1].fits());
+    }
+}
@@ -0,0 +1,291 @@
+use crate::constants;
+use crate::interaction;
+use crate::pod_handler;
+use crate::tools;
+use crate::utils;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::time::Duration;
+
+// Time to wait for a release to roll out
+const READY_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+pub struct Revision {
+    pub revision: u32,
+    pub updated: String,
+    pub status: String,
+    pub chart: String,
+    pub cpu: String,
+    pub memory: String,
+    pub image: String,
+}
+
+// Read a string or number at a JSON pointer, "-" when missing
+fn value_at(values: &Value, pointer: &str) -> String {
+    match values.pointer(pointer) {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Null) | None => "-".to_string(),
+        Some(v) => v.to_string(),
+    }
+}
+
+// Get the revisions of a release with the resources and image of each
+pub fn get_history(release: &str) -> Result<Vec<Revision>, Box<dyn Error>> {
+    let stdout = utils::run_cmd(&tools::helm(), &["history", release, "-o", "json"])?;
+    let history: Value = serde_json::from_str(&stdout)?;
+    let entries = history
+        .as_array()
+        .ok_or("Unexpected output from helm history")?;
+
+    let mut revisions = Vec::new();
+    for entry in entries {
+        let revision = entry["revision"]
+            .as_u64()
+            .ok_or("Revision number missing from helm history")? as u32;
+        let values_json = utils::run_cmd(
+            &tools::helm(),
+            &[
+                "get",
+                "values",
+                release,
+                "--revision",
+                &revision.to_string(),
+                "-o",
+                "json",
+            ],
+        )?;
+        let values: Value = serde_json::from_str(&values_json).unwrap_or(Value::Null);
+        let image = match (
+            values.pointer("/image/repository").and_then(Value::as_str),
+            values.pointer("/image/tag").and_then(Value::as_str),
+        ) {
+            (Some(repository), Some(tag)) => format!("{}:{}", repository, tag),
+            (Some(repository), None) => repository.to_string(),
+            _ => "-".to_string(),
+        };
+        revisions.push(Revision {
+            revision,
+            updated: value_at(entry, "/updated"),
+            status: value_at(entry, "/status"),
+            chart: value_at(entry, "/chart"),
+            cpu: value_at(&values, "/resources/limits/cpu"),
+            memory: value_at(&values, "/resources/limits/memory"),
+            image,
+        });
+    }
+    Ok(revisions)
+}
+
+pub fn display_history(release: &str, revisions: &[Revision]) {
+    println!("History of {}:", release);
+    println!(
+        "{:<9}{:<37}{:<12}{:<16}{:<6}{:<8}IMAGE",
+        "REVISION", "UPDATED", "STATUS", "CHART", "CPU", "MEMORY"
+    );
+    for rev in revisions {
+        println!(
+            "{:<9}{:<37}{:<12}{:<16}{:<6}{:<8}{}",
+            rev.revision, rev.updated, rev.status, rev.chart, rev.cpu, rev.memory, rev.image
+        );
+    }
+}
+
+pub fn show_history(release: &str) -> Result<(), Box<dyn Error>> {
+    let revisions = get_history(release)?;
+    display_history(release, &revisions);
+    Ok(())
+}
+
+// Roll a release back to a revision, asking for one when not given, then
+// wait for it to roll out
+pub fn rollback(release: &str, revision: Option<u32>) -> Result<(), Box<dyn Error>> {
+    let revisions = get_history(release)?;
+    let revision = match revision {
+        Some(revision) => revision,
+        None => {
+            display_history(release, &revisions);
+            print!("Revision to roll back to: ");
+            io::stdout().flush()?;
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            input
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| "Invalid input, please enter a revision number")?
+        }
+    };
+    let target = revisions
+        .iter()
+        .find(|rev| rev.revision == revision)
+        .ok_or(format!("Revision {} not found for {}", revision, release))?;
+    if !interaction::confirm(&format!(
+        "Roll {} back to revision {} (cpu {}, memory {}, image {})?",
+        release, target.revision, target.cpu, target.memory, target.image
+    ))? {
+        println!("Rollback cancelled.");
+        return Ok(());
+    }
+
+    utils::run_cmd(&tools::helm(), &["rollback", release, &revision.to_string()])?;
+    println!("Rolled {} back to revision {}.", release, revision);
+    wait_for_ready(release)
+}
+
+// Wait until the workloads of a release have rolled out the revision helm
+// just applied. Checking pod readiness alone is not enough, as the pods of the
+// previous revision stay ready until they are replaced.
+pub fn wait_for_ready(release: &str) -> Result<(), Box<dyn Error>> {
+    println!("Waiting for {} to roll out...", release);
+    let selector = format!("app.kubernetes.io/instance={}", release);
+    let stdout = utils::run_cmd(
+        &tools::kubectl(),
+        &["get", "deployments,statefulsets", "-l", &selector, "-o", "name"],
+    )?;
+    let workloads: Vec<&str> = stdout.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    if workloads.is_empty() {
+        return Err(format!("No deployment or statefulset found for {}", release).into());
+    }
+    let timeout = format!("--timeout={}s", READY_TIMEOUT.as_secs());
+    for workload in workloads {
+        utils::run_cmd(&tools::kubectl(), &["rollout", "status", workload, &timeout])
+            .map_err(|e| format!("{} did not finish rolling out: {}", workload, e))?;
+    }
+    println!("{} is ready.", release);
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct ReleaseMeta {
+    pub managed: bool,
+    pub expires_at: Option<u64>,
+}
+
+impl ReleaseMeta {
+    // Remaining lifetime for display, None when the release has no TTL
+    pub fn remaining(&self) -> Option<String> {
+        let expires_at = self.expires_at?;
+        let now = utils::unix_timestamp();
+        if expires_at <= now {
+            Some("expired".to_string())
+        } else {
+            Some(utils::format_duration(expires_at - now))
+        }
+    }
+}
+
+// Read the tool's annotations from the helm release secrets, keyed by release
+pub fn get_release_metadata() -> Result<HashMap<String, ReleaseMeta>, Box<dyn Error>> {
+    let stdout = utils::run_cmd(&tools::kubectl(), &["get", "secrets", "-l", "owner=helm", "-o", "json"])?;
+    let secrets: Value = serde_json::from_str(&stdout)?;
+    let mut releases: HashMap<String, ReleaseMeta> = HashMap::new();
+    for secret in secrets["items"].as_array().into_iter().flatten() {
+        let Some(release) = secret.pointer("/metadata/labels/name").and_then(Value::as_str) else {
+            continue;
+        };
+        let meta = releases.entry(release.to_string()).or_default();
+        let annotations = &secret["metadata"]["annotations"];
+        if annotations[constants::MANAGED_BY_ANNOTATION].as_str() == Some(constants::MANAGED_BY_VALUE) {
+            meta.managed = true;
+        }
+        // Revisions created by upgrades or rollbacks carry no annotations,
+        // so keep the latest deadline found on any of them
+        if let Some(expires_at) = annotations[constants::EXPIRES_AT_ANNOTATION]
+            .as_str()
+            .and_then(|v| v.parse::<u64>().ok())
+        {
+            meta.expires_at = Some(meta.expires_at.map_or(expires_at, |e| e.max(expires_at)));
+        }
+    }
+    Ok(releases)
+}
+
+fn annotate_release(release: &str, annotations: &[String]) -> Result<(), Box<dyn Error>> {
+    let selector = format!("owner=helm,name={}", release);
+    let mut args = vec!["annotate", "secrets", "-l", &selector, "--overwrite"];
+    args.extend(annotations.iter().map(|a| a.as_str()));
+    utils::run_cmd(&tools::kubectl(), &args)?;
+    Ok(())
+}
+
+// Mark a release as installed by this tool, optionally with an expiry
+pub fn mark_managed(release: &str, ttl: Option<Duration>) -> Result<(), Box<dyn Error>> {
+    let mut annotations = vec![format!(
+        "{}={}",
+        constants::MANAGED_BY_ANNOTATION,
+        constants::MANAGED_BY_VALUE
+    )];
+    if let Some(ttl) = ttl {
+        let expires_at = utils::unix_timestamp().saturating_add(ttl.as_secs());
+        annotations.push(format!("{}={}", constants::EXPIRES_AT_ANNOTATION, expires_at));
+        println!("{} expires in {}.", release, utils::format_duration(ttl.as_secs()));
+    }
+    annotate_release(release, &annotations)
+}
+
+// Push the expiry of a release out by `by`, counting from now when the
+// release has no deadline or has already expired
+pub fn extend(release: &str, by: Duration) -> Result<(), Box<dyn Error>> {
+    let releases = get_release_metadata()?;
+    let meta = releases
+        .get(release)
+        .ok_or(format!("Release {} not found", release))?;
+    let now = utils::unix_timestamp();
+    let expires_at = meta.expires_at.unwrap_or(now).max(now).saturating_add(by.as_secs());
+    annotate_release(
+        release,
+        &[format!("{}={}", constants::EXPIRES_AT_ANNOTATION, expires_at)],
+    )?;
+    println!(
+        "{} now expires in {}.",
+        release,
+        utils::format_duration(expires_at - now)
+    );
+    Ok(())
+}
+
+// Uninstall the expired releases installed by this tool
+pub fn reap(dry_run: bool, assume_yes: bool) -> Result<(), Box<dyn Error>> {
+    let now = utils::unix_timestamp();
+    let mut expired: Vec<(String, u64)> = get_release_metadata()?
+        .into_iter()
+        .filter(|(_, meta)| meta.managed)
+        .filter_map(|(release, meta)| meta.expires_at.map(|e| (release, e)))
+        .filter(|(_, expires_at)| *expires_at <= now)
+        .collect();
+    expired.sort();
+
+    if expired.is_empty() {
+        println!("No expired pods.");
+        return Ok(());
+    }
+    for (release, expires_at) in &expired {
+        println!(
+            "Warning: {} expired {} ago and will be uninstalled.",
+            release,
+            utils::format_duration(now - expires_at)
+        );
+    }
+    if dry_run {
+        println!("Dry run, nothing uninstalled.");
+        return Ok(());
+    }
+    if !assume_yes && !interaction::confirm("Uninstall them?")? {
+        println!("Nothing uninstalled.");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for (release, _) in &expired {
+        if let Err(e) = pod_handler::uninstall_release(release, false) {
+            eprintln!("Error uninstalling {}: {}", release, e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} expired pods could not be uninstalled", failed).into());
+    }
+    Ok(())
+}
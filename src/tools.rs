@@ -0,0 +1,514 @@
+use crate::checksum::{self, Verification};
+use crate::{constants, platform, utils};
+use clap::ValueEnum;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Command line tools managed in bin/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Tool {
+    Kubectl,
+    Helm,
+}
+
+pub const ALL_TOOLS: [Tool; 2] = [Tool::Kubectl, Tool::Helm];
+
+impl Tool {
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Kubectl => "kubectl",
+            Tool::Helm => "helm",
+        }
+    }
+
+    // Version this release of the tool is built against
+    pub fn configured_version(self) -> &'static str {
+        match self {
+            Tool::Kubectl => constants::KUBECTL_VERSION,
+            Tool::Helm => constants::HELM_VERSION,
+        }
+    }
+
+    // Environment variable with the path of a binary to use instead of bin/
+    pub fn path_env(self) -> &'static str {
+        match self {
+            Tool::Kubectl => constants::KUBECTL_PATH_ENV,
+            Tool::Helm => constants::HELM_PATH_ENV,
+        }
+    }
+
+    fn download(self, dir: &Path, version: &str) -> Result<(), Box<dyn Error>> {
+        match self {
+            Tool::Kubectl => utils::download_kubectl(dir, version),
+            Tool::Helm => utils::download_helm(dir, version),
+        }
+    }
+}
+
+// Where a tool binary was found
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToolSource {
+    // Path set in the tool's environment variable
+    Configured,
+    // The bin/ directory managed by this tool
+    BinDir,
+    // The system PATH
+    SystemPath,
+}
+
+impl fmt::Display for ToolSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ToolSource::Configured => write!(f, "configured path"),
+            ToolSource::BinDir => write!(f, "bin directory"),
+            ToolSource::SystemPath => write!(f, "system PATH"),
+        }
+    }
+}
+
+// Find a tool binary: the path configured in its environment variable, then
+// bin/, then the system PATH
+pub fn locate(tool: Tool) -> Option<(PathBuf, ToolSource)> {
+    if let Ok(path) = std::env::var(tool.path_env()) {
+        let path = PathBuf::from(path);
+        if path.is_file() {
+            return Some((path, ToolSource::Configured));
+        }
+    }
+    if let Ok(bin_dir) = bin_dir() {
+        let path = platform::get_bin_path(&bin_dir, tool.name());
+        if path.is_file() {
+            return Some((path, ToolSource::BinDir));
+        }
+    }
+    let exe = platform::get_exe_name(tool.name());
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(&exe))
+            .find(|path| path.is_file())
+            .map(|path| (path, ToolSource::SystemPath))
+    })
+}
+
+// Program to run for a tool, by absolute path when it was found; the bare
+// name otherwise, so running it fails with the usual "not found" error
+pub fn command(tool: Tool) -> String {
+    match locate(tool) {
+        Some((path, _)) => path.to_string_lossy().to_string(),
+        None => tool.name().to_string(),
+    }
+}
+
+pub fn kubectl() -> String {
+    command(Tool::Kubectl)
+}
+
+pub fn helm() -> String {
+    command(Tool::Helm)
+}
+
+// Directory of the tool binaries used by this tool
+pub fn bin_dir() -> std::io::Result<PathBuf> {
+    Ok(std::env::current_dir()?.join("bin"))
+}
+
+// Directory holding the installed versions of the tools
+fn versions_dir(bin_dir: &Path) -> PathBuf {
+    bin_dir.join("versions")
+}
+
+// Directory of one installed version of a tool
+fn version_dir(bin_dir: &Path, tool: Tool, version: &str) -> PathBuf {
+    versions_dir(bin_dir).join(tool.name()).join(version)
+}
+
+// Versions are written with a leading "v" like the releases
+fn normalize_version(version: &str) -> String {
+    format!("v{}", version.trim().trim_start_matches('v'))
+}
+
+// Version of a tool binary, from `kubectl version --client -o json` or
+// `helm version --short`
+pub fn detect_version(tool: Tool, binary: &Path) -> Option<String> {
+    let binary = binary.to_string_lossy();
+    match tool {
+        Tool::Kubectl => {
+            let output = utils::run_cmd(&binary, &["version", "--client", "-o", "json"]).ok()?;
+            parse_kubectl_version(&output)
+        }
+        Tool::Helm => {
+            let output = utils::run_cmd(&binary, &["version", "--short"]).ok()?;
+            parse_helm_version(&output)
+        }
+    }
+}
+
+fn parse_kubectl_version(output: &str) -> Option<String> {
+    let json: Value = serde_json::from_str(output).ok()?;
+    json.pointer("/clientVersion/gitVersion")?
+        .as_str()
+        .map(|v| v.to_string())
+}
+
+// "v3.12.3+g3a31588" -> "v3.12.3"
+fn parse_helm_version(output: &str) -> Option<String> {
+    let version = output.trim().split('+').next()?;
+    if version.starts_with('v') {
+        Some(version.to_string())
+    } else {
+        None
+    }
+}
+
+// Pinned versions, one "<tool> <version>" per line
+fn read_pins(bin_dir: &Path) -> Vec<(String, String)> {
+    fs::read_to_string(versions_dir(bin_dir).join("pins"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let (tool, version) = line.trim().split_once(' ')?;
+            Some((tool.to_string(), version.trim().to_string()))
+        })
+        .collect()
+}
+
+fn write_pins(bin_dir: &Path, pins: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(versions_dir(bin_dir))?;
+    let content: String = pins
+        .iter()
+        .map(|(tool, version)| format!("{} {}\n", tool, version))
+        .collect();
+    fs::write(versions_dir(bin_dir).join("pins"), content)?;
+    Ok(())
+}
+
+pub fn pinned_version(bin_dir: &Path, tool: Tool) -> Option<String> {
+    read_pins(bin_dir)
+        .into_iter()
+        .find(|(name, _)| name == tool.name())
+        .map(|(_, version)| version)
+}
+
+// Version a tool should be at: the pinned one, otherwise the configured one
+pub fn desired_version(bin_dir: &Path, tool: Tool) -> String {
+    pinned_version(bin_dir, tool).unwrap_or_else(|| tool.configured_version().to_string())
+}
+
+// Versions of a tool installed side by side, oldest first
+fn installed_versions(bin_dir: &Path, tool: Tool) -> Vec<String> {
+    let mut versions: Vec<String> = fs::read_dir(versions_dir(bin_dir).join(tool.name()))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| platform::get_bin_path(&entry.path(), tool.name()).exists())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    versions.sort_by_key(|v| {
+        v.trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse::<u32>().unwrap_or(0))
+            .collect::<Vec<_>>()
+    });
+    versions
+}
+
+// Download a version of a tool next to the other installed versions. A copy
+// already there is kept when it still matches its release and downloaded
+// again otherwise.
+pub fn install(bin_dir: &Path, tool: Tool, version: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let version = normalize_version(version);
+    let dir = version_dir(bin_dir, tool, &version);
+    let binary = platform::get_bin_path(&dir, tool.name());
+    if binary.exists() {
+        match verify_version(bin_dir, tool, &version) {
+            Ok(Verification::Verified) => {
+                println!("{} {} is already installed", tool.name(), version);
+                return Ok(binary);
+            }
+            Ok(_) => eprintln!(
+                "Warning: the installed {} {} does not match its release, downloading it again",
+                tool.name(),
+                version
+            ),
+            Err(e) => eprintln!(
+                "Could not verify the installed {} {} ({}), downloading it again",
+                tool.name(),
+                version,
+                e
+            ),
+        }
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+    if let Err(e) = tool.download(&dir, &version) {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(e);
+    }
+    println!("Installed {} {}", tool.name(), version);
+    Ok(binary)
+}
+
+// Install a version of a tool from a local file, e.g. from an offline
+// bundle, after checking it against `sha256`, and use it. The release
+// archive it was extracted from, if any, is kept next to it so it can be
+// verified again later.
+pub fn install_from_file(
+    bin_dir: &Path,
+    tool: Tool,
+    version: &str,
+    source: &Path,
+    sha256: &str,
+    archive: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let version = normalize_version(version);
+    let dir = version_dir(bin_dir, tool, &version);
+    let binary = platform::get_bin_path(&dir, tool.name());
+    fs::create_dir_all(&dir)?;
+    let result = fs::copy(source, &binary)
+        .map_err(Into::into)
+        .and_then(|_| checksum::verify_download(&binary, sha256))
+        .and_then(|_| Ok(platform::set_executable(&binary)?))
+        .and_then(|_| {
+            if let Some(archive) = archive {
+                fs::copy(archive, dir.join(archive.file_name().unwrap_or_default()))?;
+            }
+            Ok(())
+        });
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&dir);
+    }
+    result?;
+    println!(
+        "Installed {} {} from {}",
+        tool.name(),
+        version,
+        source.display()
+    );
+    use_version(bin_dir, tool, &version, sha256)
+}
+
+// Check an installed version of a tool against the trusted checksum of its
+// release: kubectl directly, helm through the release archive kept next to
+// it, which must hold the very same binary
+pub fn verify_version(
+    bin_dir: &Path,
+    tool: Tool,
+    version: &str,
+) -> Result<Verification, Box<dyn Error>> {
+    let (os, arch) = utils::get_os_and_arch()?;
+    let dir = version_dir(bin_dir, tool, version);
+    let binary = platform::get_bin_path(&dir, tool.name());
+    match tool {
+        Tool::Kubectl => {
+            let expected = utils::trusted_kubectl_checksum(version, &os, &arch)?;
+            Ok(checksum::verify_file(&binary, &expected)?)
+        }
+        Tool::Helm => {
+            let archive = dir.join(utils::helm_artifact(version, &os, &arch));
+            if !archive.exists() {
+                return Err(format!(
+                    "The release archive of helm {} is missing, reinstall it to verify it",
+                    version
+                )
+                .into());
+            }
+            let expected = utils::trusted_helm_checksum(version, &os, &arch)?;
+            let verification = checksum::verify_file(&archive, &expected)?;
+            if verification != Verification::Verified {
+                return Ok(verification);
+            }
+            let extracted = dir.join(".helm.verify");
+            let result =
+                utils::extract_binary(&archive, &utils::helm_archive_entry(&os, &arch), &extracted)
+                    .and_then(|_| Ok(checksum::sha256_file(&extracted)?));
+            let _ = fs::remove_file(&extracted);
+            Ok(checksum::verify_file(&binary, &result?)?)
+        }
+    }
+}
+
+// Check the binary of a tool in bin/: it must be a copy of an installed
+// version that still matches the trusted checksum of its release
+pub fn verify_installed(bin_dir: &Path, tool: Tool) -> Result<Verification, Box<dyn Error>> {
+    let actual = checksum::sha256_file(&platform::get_bin_path(bin_dir, tool.name()))?;
+    for version in installed_versions(bin_dir, tool).iter().rev() {
+        let cached = platform::get_bin_path(&version_dir(bin_dir, tool, version), tool.name());
+        if checksum::sha256_file(&cached).is_ok_and(|sha256| sha256 == actual) {
+            return verify_version(bin_dir, tool, version);
+        }
+    }
+    Ok(Verification::Unknown)
+}
+
+// Make an installed version the one used from bin/, once it is verified
+// against the trusted checksum of its release
+pub fn activate(bin_dir: &Path, tool: Tool, version: &str) -> Result<(), Box<dyn Error>> {
+    let version = normalize_version(version);
+    let source = platform::get_bin_path(&version_dir(bin_dir, tool, &version), tool.name());
+    if !source.exists() {
+        return Err(format!("{} {} is not installed", tool.name(), version).into());
+    }
+    // A cached version that was tampered with or corrupted must not be used
+    if let Verification::Mismatch { expected, actual } = verify_version(bin_dir, tool, &version)? {
+        return Err(format!(
+            "{} {} does not match its release (expected {}, got {}), reinstall it",
+            tool.name(),
+            version,
+            expected,
+            actual
+        )
+        .into());
+    }
+    use_version(bin_dir, tool, &version, &checksum::sha256_file(&source)?)
+}
+
+// Copy an installed version, whose checksum is `sha256`, into bin/
+fn use_version(
+    bin_dir: &Path,
+    tool: Tool,
+    version: &str,
+    sha256: &str,
+) -> Result<(), Box<dyn Error>> {
+    let source = platform::get_bin_path(&version_dir(bin_dir, tool, version), tool.name());
+    let target = platform::get_bin_path(bin_dir, tool.name());
+    let staged = bin_dir.join(format!(".{}.new", tool.name()));
+    let result = fs::copy(&source, &staged)
+        .map_err(Into::into)
+        .and_then(|_| checksum::verify_download(&staged, sha256))
+        .and_then(|_| Ok(platform::set_executable(&staged)?))
+        .and_then(|_| Ok(fs::rename(&staged, &target)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    result?;
+    println!("Now using {} {}", tool.name(), version);
+    Ok(())
+}
+
+// Install the desired version of a tool when the one in bin/ differs, and
+// use it
+pub fn upgrade(bin_dir: &Path, tool: Tool) -> Result<(), Box<dyn Error>> {
+    let desired = desired_version(bin_dir, tool);
+    let current = detect_version(tool, &platform::get_bin_path(bin_dir, tool.name()));
+    if current.as_deref() == Some(desired.as_str()) {
+        println!("{} is up to date ({})", tool.name(), desired);
+        return Ok(());
+    }
+    install(bin_dir, tool, &desired)?;
+    activate(bin_dir, tool, &desired)
+}
+
+// Show the version in use, the configured and pinned ones and the installed
+// versions of each tool
+pub fn list(bin_dir: &Path) -> Result<(), Box<dyn Error>> {
+    for tool in ALL_TOOLS {
+        let current = detect_version(tool, &platform::get_bin_path(bin_dir, tool.name()));
+        println!("{}:", tool.name());
+        println!(
+            "  In use:     {}",
+            current.as_deref().unwrap_or("not installed")
+        );
+        println!("  Configured: {}", tool.configured_version());
+        if let Some(pinned) = pinned_version(bin_dir, tool) {
+            println!("  Pinned:     {}", pinned);
+        }
+        let installed = installed_versions(bin_dir, tool);
+        if !installed.is_empty() {
+            println!("  Installed:  {}", installed.join(", "));
+        }
+    }
+    Ok(())
+}
+
+// Pin a tool to a version, installing and using it, or remove the pin when
+// no version is given
+pub fn pin(bin_dir: &Path, tool: Tool, version: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let mut pins = read_pins(bin_dir);
+    pins.retain(|(name, _)| name != tool.name());
+    match version {
+        Some(version) => {
+            let version = normalize_version(version);
+            install(bin_dir, tool, &version)?;
+            activate(bin_dir, tool, &version)?;
+            pins.push((tool.name().to_string(), version.clone()));
+            write_pins(bin_dir, &pins)?;
+            println!("{} pinned to {}", tool.name(), version);
+        }
+        None => {
+            write_pins(bin_dir, &pins)?;
+            println!(
+                "{} unpinned, `tools upgrade` moves it to {}",
+                tool.name(),
+                tool.configured_version()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_versions() {
+        let kubectl = r#"{"clientVersion": {"major": "1", "minor": "28", "gitVersion": "v1.28.4"}, "kustomizeVersion": "v5.0.4"}"#;
+        assert_eq!(parse_kubectl_version(kubectl), Some("v1.28.4".to_string()));
+        assert_eq!(parse_kubectl_version("error: unknown flag"), None);
+        assert_eq!(
+            parse_helm_version("v3.12.3+g3a31588\n"),
+            Some("v3.12.3".to_string())
+        );
+        assert_eq!(parse_helm_version("Error"), None);
+        assert_eq!(normalize_version("1.28.4"), "v1.28.4");
+    }
+
+    #[test]
+    fn test_verify_against_trusted_checksums() {
+        let bin_dir = std::env::temp_dir().join(format!("thumed_tools_{}", std::process::id()));
+        fs::create_dir_all(&bin_dir).unwrap();
+        let source = bin_dir.join("download");
+        fs::write(&source, "kubectl").unwrap();
+        let sha256 = checksum::sha256_file(&source).unwrap();
+        let (os, arch) = utils::get_os_and_arch().unwrap();
+        let trusted = bin_dir.join("trusted_checksums.sha256");
+        let artifact = utils::kubectl_artifact("v1.28.4", &os, &arch);
+        fs::write(&trusted, format!("{}  {}\n", sha256, artifact)).unwrap();
+        unsafe {
+            std::env::set_var(constants::TRUSTED_CHECKSUMS_ENV, &trusted);
+        }
+
+        install_from_file(&bin_dir, Tool::Kubectl, "v1.28.4", &source, &sha256, None).unwrap();
+        assert_eq!(
+            verify_installed(&bin_dir, Tool::Kubectl).unwrap(),
+            Verification::Verified
+        );
+        activate(&bin_dir, Tool::Kubectl, "v1.28.4").unwrap();
+
+        // A binary in bin/ that is no copy of an installed version is not trusted
+        let target = platform::get_bin_path(&bin_dir, "kubectl");
+        fs::write(&target, "tampered").unwrap();
+        assert_eq!(
+            verify_installed(&bin_dir, Tool::Kubectl).unwrap(),
+            Verification::Unknown
+        );
+
+        // Neither is one whose cached copy was changed along with it
+        let cached =
+            platform::get_bin_path(&version_dir(&bin_dir, Tool::Kubectl, "v1.28.4"), "kubectl");
+        fs::write(&cached, "tampered").unwrap();
+        assert!(matches!(
+            verify_installed(&bin_dir, Tool::Kubectl).unwrap(),
+            Verification::Mismatch { .. }
+        ));
+        assert!(activate(&bin_dir, Tool::Kubectl, "v1.28.4").is_err());
+
+        unsafe {
+            std::env::remove_var(constants::TRUSTED_CHECKSUMS_ENV);
+        }
+        fs::remove_dir_all(&bin_dir).unwrap();
+    }
+}
@@ -1,7 +1,13 @@
+use flate2::read::GzDecoder;
 use std::error::Error;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
 use std::process::Command;
-use crate::{constants, platform};
+use tar::Archive;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::download::Downloader;
+use crate::{checksum, constants, platform};
 // Run a command and return its output as a string
 // Returns an error if the command fails or if stdout cannot be converted to a string
 pub fn run_cmd(cmd: &str, args: &[&str]) -> Result<String, Box<dyn Error>> {
@@ -17,50 +23,64 @@ pub fn run_cmd(cmd: &str, args: &[&str]) -> Result<String, Box<dyn Error>> {
     Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
-pub fn download_file(url: &str, output_path: &Path) -> Result<(), Box<dyn Error>> {
-    println!("Downloading from: {}", url);
-
-    if platform::is_windows() {
-        // Use PowerShell on Windows
-        let status = Command::new("powershell")
-            .arg("-Command")
-            .arg(&format!(
-                "Invoke-WebRequest -Uri '{}' -OutFile '{}'",
-                url,
-                output_path.to_string_lossy()
-            ))
-            .status()?;
-
-        if !status.success() {
-            return Err(format!("Failed to download file from {}", url).into());
-        }
-    } else {
-        // Use curl on Unix-like systems
-        let status = Command::new("curl")
-            .args(&["-L", "-o", &output_path.to_string_lossy(), url])
-            .status()?;
-
-        if !status.success() {
-            return Err(format!("Failed to download file from {}", url).into());
-        }
-    }
+// Seconds since the Unix epoch, used to timestamp archived files
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
 
-    // Make the file executable on Unix-like systems
-    if platform::is_unix() {
-        let status = Command::new("chmod")
-            .args(&["+x", &output_path.to_string_lossy()])
-            .status()?;
+// Parse a duration such as "90m", "12h", "14d" or "2w"
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration: {}", input))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" | "" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("Invalid duration unit in {} (use s, m, h, d or w)", input)),
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Duration too large: {}", input))
+}
 
-        if !status.success() {
-            return Err("Failed to make the file executable".into());
-        }
+// Format a number of seconds as "3d 4h", "5h 10m" or "42m"
+pub fn format_duration(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
     }
+}
 
+pub fn download_file(url: &str, output_path: &Path) -> Result<(), Box<dyn Error>> {
+    println!("Downloading from: {}", url);
+    Downloader::default().download(url, output_path)?;
     println!("Download complete: {}", output_path.display());
     Ok(())
 }
 
-fn get_os_and_arch() -> Result<(String, String), Box<dyn Error>> {
+// Fetch a small text file such as a checksum file
+pub fn fetch_text(url: &str) -> Result<String, Box<dyn Error>> {
+    Downloader::default().fetch_text(url)
+}
+
+// OS and architecture of this machine, named like the tool releases
+pub fn get_os_and_arch() -> Result<(String, String), Box<dyn Error>> {
     let os = if platform::is_windows() {
         "windows".to_string()
     } else if cfg!(target_os = "macos") {
@@ -82,8 +102,134 @@ fn get_os_and_arch() -> Result<(String, String), Box<dyn Error>> {
     Ok((os, arch))
 }
 
-pub fn download_kubectl(bin_dir: &Path) -> Result<(), Box<dyn Error>> {
-    let kubectl_path = platform::get_bin_path(bin_dir, "kubectl");
+// Mirror base URLs of a tool in the order they are tried: the comma separated
+// list in `env` when set, otherwise `defaults`
+fn mirrors(env: &str, defaults: &[&str]) -> Vec<String> {
+    if let Ok(list) = std::env::var(env) {
+        let list: Vec<String> = list
+            .split(',')
+            .map(|m| m.trim().trim_end_matches('/').to_string())
+            .filter(|m| !m.is_empty())
+            .collect();
+        if !list.is_empty() {
+            return list;
+        }
+    }
+    defaults.iter().map(|m| m.to_string()).collect()
+}
+
+// Checksum file URLs of `file_path` on the official `sources`, HTTPS only
+fn checksum_urls(sources: &[&str], file_path: &str, checksum_suffix: &str) -> Vec<String> {
+    sources
+        .iter()
+        .filter(|source| source.starts_with("https://"))
+        .map(|source| format!("{}/{}{}", source, file_path, checksum_suffix))
+        .collect()
+}
+
+// Download `file_path` from the first mirror that serves it and whose copy
+// matches `expected`, the trusted checksum of the artifact. The checksum never
+// comes from the mirrors, so a mirror cannot vouch for its own copy.
+fn download_from_mirrors(
+    mirrors: &[String],
+    file_path: &str,
+    expected: &str,
+    artifact: &str,
+    output_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    for mirror in mirrors {
+        let result = download_file(&format!("{}/{}", mirror, file_path), output_path)
+            .and_then(|_| checksum::verify_download(output_path, expected));
+        match result {
+            Ok(()) => {
+                println!("Downloaded {} from {}", artifact, mirror);
+                return Ok(());
+            }
+            Err(e) => eprintln!("Mirror {} failed: {}", mirror, e),
+        }
+    }
+    Err(format!("{} could not be downloaded from any mirror", artifact).into())
+}
+
+// Executable file name of a tool on `os`
+pub fn exe_name_for(os: &str, name: &str) -> String {
+    if os == "windows" {
+        format!("{}.exe", name)
+    } else {
+        name.to_string()
+    }
+}
+
+// Name of a kubectl release, as used for its pinned checksum
+pub fn kubectl_artifact(version: &str, os: &str, arch: &str) -> String {
+    format!("kubectl-{}-{}-{}", version, os, arch)
+}
+
+// Name of a helm release archive: a zip on Windows, a tarball elsewhere
+pub fn helm_artifact(version: &str, os: &str, arch: &str) -> String {
+    let extension = if os == "windows" { "zip" } else { "tar.gz" };
+    format!("helm-{}-{}-{}.{}", version, os, arch, extension)
+}
+
+// Path of the helm binary inside its release archive, next to the license
+// and readme
+pub fn helm_archive_entry(os: &str, arch: &str) -> String {
+    format!("{}-{}/{}", os, arch, exe_name_for(os, "helm"))
+}
+
+// Path of a kubectl release below the mirror base URLs
+fn kubectl_file_path(version: &str, os: &str, arch: &str) -> String {
+    format!(
+        "{}/bin/{}/{}/{}",
+        version,
+        os,
+        arch,
+        exe_name_for(os, "kubectl")
+    )
+}
+
+// Trusted checksum of a kubectl release binary
+pub fn trusted_kubectl_checksum(
+    version: &str,
+    os: &str,
+    arch: &str,
+) -> Result<String, Box<dyn Error>> {
+    checksum::expected_checksum(
+        &kubectl_artifact(version, os, arch),
+        &checksum_urls(
+            constants::KUBECTL_MIRRORS,
+            &kubectl_file_path(version, os, arch),
+            ".sha256",
+        ),
+    )
+}
+
+// Trusted checksum of a helm release archive
+pub fn trusted_helm_checksum(
+    version: &str,
+    os: &str,
+    arch: &str,
+) -> Result<String, Box<dyn Error>> {
+    let filename = helm_artifact(version, os, arch);
+    checksum::expected_checksum(
+        &filename,
+        &checksum_urls(constants::HELM_MIRRORS, &filename, ".sha256sum"),
+    )
+}
+
+pub fn download_kubectl(bin_dir: &Path, version: &str) -> Result<(), Box<dyn Error>> {
+    let (os, arch) = get_os_and_arch()?;
+    download_kubectl_for(bin_dir, version, &os, &arch)
+}
+
+// Download kubectl for another OS and architecture, e.g. to build a bundle
+pub fn download_kubectl_for(
+    bin_dir: &Path,
+    version: &str,
+    os: &str,
+    arch: &str,
+) -> Result<(), Box<dyn Error>> {
+    let kubectl_path = bin_dir.join(exe_name_for(os, "kubectl"));
 
     if kubectl_path.exists() {
         println!("kubectl already exists, skipping download");
@@ -92,29 +238,36 @@ pub fn download_kubectl(bin_dir: &Path) -> Result<(), Box<dyn Error>> {
 
     println!("Downloading kubectl...");
 
-    let (os, arch) = get_os_and_arch()?;
-    let version = constants::KUBECTL_VERSION; // Use a stable version
-
-    let download_url = if platform::is_windows() {
-        format!(
-            "https://dl.k8s.io/release/{}/bin/windows/{}/kubectl.exe",
-            version, arch
-        )
-    } else {
-        format!(
-            "https://dl.k8s.io/release/{}/bin/{}/{}/kubectl",
-            version, os, arch
-        )
-    };
-
-    download_file(&download_url, &kubectl_path)?;
+    let expected = trusted_kubectl_checksum(version, os, arch)?;
+    let mirrors = mirrors(constants::KUBECTL_MIRRORS_ENV, constants::KUBECTL_MIRRORS);
+    download_from_mirrors(
+        &mirrors,
+        &kubectl_file_path(version, os, arch),
+        &expected,
+        &kubectl_artifact(version, os, arch),
+        &kubectl_path,
+    )?;
+    platform::set_executable(&kubectl_path)?;
 
     println!("kubectl downloaded successfully");
     Ok(())
 }
 
-pub fn download_helm(bin_dir: &Path) -> Result<(), Box<dyn Error>> {
-    let helm_path = platform::get_bin_path(bin_dir, "helm");
+pub fn download_helm(bin_dir: &Path, version: &str) -> Result<(), Box<dyn Error>> {
+    let (os, arch) = get_os_and_arch()?;
+    download_helm_for(bin_dir, version, &os, &arch)
+}
+
+// Download helm for another OS and architecture, e.g. to build a bundle. The
+// verified release archive is kept next to the binary, as the official
+// checksums only cover the archive.
+pub fn download_helm_for(
+    bin_dir: &Path,
+    version: &str,
+    os: &str,
+    arch: &str,
+) -> Result<(), Box<dyn Error>> {
+    let helm_path = bin_dir.join(exe_name_for(os, "helm"));
 
     if helm_path.exists() {
         println!("helm already exists, skipping download");
@@ -123,11 +276,8 @@ pub fn download_helm(bin_dir: &Path) -> Result<(), Box<dyn Error>> {
 
     println!("Downloading helm...");
 
-    let (os, arch) = get_os_and_arch()?;
-    let version = constants::HELM_VERSION; // Use a stable version
-
     // Adjust OS name to match Helm's naming convention
-    let helm_os = match os.as_str() {
+    let helm_os = match os {
         "darwin" => "darwin",
         "linux" => "linux",
         "windows" => "windows",
@@ -135,64 +285,228 @@ pub fn download_helm(bin_dir: &Path) -> Result<(), Box<dyn Error>> {
     };
 
     // Adjust architecture name to match Helm's naming convention
-    let helm_arch = match arch.as_str() {
+    let helm_arch = match arch {
         "amd64" => "amd64",
         "arm64" => "arm64",
         _ => return Err(format!("Unsupported architecture: {}", arch).into()),
     };
 
-    let filename = format!("helm-{}-{}-{}", version, helm_os, helm_arch);
-    let download_url = format!("https://get.helm.sh/{}.tar.gz", filename);
+    let filename = helm_artifact(version, helm_os, helm_arch);
+    let expected = trusted_helm_checksum(version, helm_os, helm_arch)?;
+    let mirrors = mirrors(constants::HELM_MIRRORS_ENV, constants::HELM_MIRRORS);
 
-    let temp_file = bin_dir.join(format!("{}.tar.gz", filename));
-    download_file(&download_url, &temp_file)?;
+    let archive_path = bin_dir.join(&filename);
+    let result = download_from_mirrors(&mirrors, &filename, &expected, &filename, &archive_path)
+        .and_then(|_| {
+            extract_binary(
+                &archive_path,
+                &helm_archive_entry(helm_os, helm_arch),
+                &helm_path,
+            )
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&archive_path);
+    }
+    result?;
 
-    // Extract binary from the tarball
-    extract_gz_file(&temp_file, &helm_path)?;
-    let extracted_dir = bin_dir.join(format!("{}-{}", helm_os, helm_arch));
-    let extracted_file = extracted_dir.join(if cfg!(windows) { "helm.exe" } else { "helm" });
-    std::fs::rename(extracted_file, &helm_path)?;
     println!("helm downloaded successfully");
     Ok(())
 }
-fn extract_gz_file(gz_path: &Path, output_path: &Path) -> Result<(), Box<dyn Error>> {
-    println!("Extracting: {}", gz_path.display());
 
-    let extract_dir = gz_path.parent().unwrap();
-    
-    if platform::is_windows() {
-        // Use PowerShell on Windows to extract .tar.gz
-        let status = Command::new("powershell")
-            .arg("-Command")
-            .arg(&format!(
-                "tar -xzf '{}' -C '{}'",
-                gz_path.to_string_lossy(),
-                extract_dir.to_string_lossy()
-            ))
-            .status()?;
-
-        if !status.success() {
-            return Err(format!("Failed to extract file {}", gz_path.display()).into());
-        }
+// Whether an archive entry path stays inside the extraction directory
+pub fn is_safe_entry_path(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+// Extract the single file `entry` of a .tar.gz or .zip archive to
+// `output_path` and make it executable. Other entries are skipped, and nothing
+// is left behind when the entry is missing or extraction fails.
+pub fn extract_binary(
+    archive_path: &Path,
+    entry: &str,
+    output_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    println!("Extracting {} from {}", entry, archive_path.display());
+    let found = if archive_path.extension().is_some_and(|ext| ext == "zip") {
+        extract_from_zip(archive_path, Path::new(entry), output_path)?
     } else {
-        // Use tar on Unix-like systems
-        let status = Command::new("tar")
-            .args(&[
-                "-xzf",
-                &gz_path.to_string_lossy(),
-                "-C",
-                &extract_dir.to_string_lossy(),
-            ])
-            .status()?;
-
-        if !status.success() {
-            return Err(format!("Failed to extract file {}", gz_path.display()).into());
+        extract_from_tar_gz(archive_path, Path::new(entry), output_path)?
+    };
+    if !found {
+        return Err(format!("{} not found in {}", entry, archive_path.display()).into());
+    }
+    println!("Extraction complete to: {}", output_path.display());
+    Ok(())
+}
+
+/// Error for an archive entry that would be written outside the archive
+fn unsafe_entry_error(archive_path: &Path, path: &Path) -> Box<dyn Error> {
+    format!(
+        "Refusing to extract {}: entry {} points outside the archive",
+        archive_path.display(),
+        path.display()
+    )
+    .into()
+}
+
+/// Error for a wanted entry that is a directory, link or other special file
+fn not_a_file_error(archive_path: &Path, entry: &Path) -> Box<dyn Error> {
+    format!(
+        "{} in {} is not a regular file",
+        entry.display(),
+        archive_path.display()
+    )
+    .into()
+}
+
+/// Extract `wanted` from a .tar.gz archive, returning whether it was found
+fn extract_from_tar_gz(
+    archive_path: &Path,
+    wanted: &Path,
+    output_path: &Path,
+) -> Result<bool, Box<dyn Error>> {
+    let mut archive = Archive::new(GzDecoder::new(File::open(archive_path)?));
+    for file in archive.entries()? {
+        let mut file = file?;
+        let path = file.path()?.into_owned();
+        if !is_safe_entry_path(&path) {
+            return Err(unsafe_entry_error(archive_path, &path));
+        }
+        let path: PathBuf = path
+            .components()
+            .filter(|c| *c != Component::CurDir)
+            .collect();
+        if path != wanted {
+            continue;
         }
+        if !file.header().entry_type().is_file() {
+            return Err(not_a_file_error(archive_path, wanted));
+        }
+        write_executable(&mut file, output_path)?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Extract `wanted` from a .zip archive, returning whether it was found
+fn extract_from_zip(
+    archive_path: &Path,
+    wanted: &Path,
+    output_path: &Path,
+) -> Result<bool, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(File::open(archive_path)?)?;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        let path = PathBuf::from(file.name());
+        if !is_safe_entry_path(&path) || file.enclosed_name().is_none() {
+            return Err(unsafe_entry_error(archive_path, &path));
+        }
+        let path: PathBuf = path
+            .components()
+            .filter(|c| *c != Component::CurDir)
+            .collect();
+        if path != wanted {
+            continue;
+        }
+        if !file.is_file() || file.is_symlink() {
+            return Err(not_a_file_error(archive_path, wanted));
+        }
+        write_executable(&mut file, output_path)?;
+        return Ok(true);
     }
+    Ok(false)
+}
 
-    // Clean up the downloaded archive
-    std::fs::remove_file(gz_path)?;
+/// Write `reader` next to `output_path` and move it in place once complete
+fn write_executable(reader: &mut impl io::Read, output_path: &Path) -> io::Result<()> {
+    let mut temp_path = output_path.as_os_str().to_owned();
+    temp_path.push(".extract");
+    let temp_path = PathBuf::from(temp_path);
+    let result = File::create(&temp_path)
+        .and_then(|mut out| io::copy(reader, &mut out).map(|_| ()))
+        .and_then(|_| platform::set_executable(&temp_path))
+        .and_then(|_| fs::rename(&temp_path, output_path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
 
-    println!("Extraction complete to: {}", output_path.display());
-    Ok(())
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("14d"), Ok(Duration::from_secs(14 * 86400)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86400)));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("99999999999999999d").is_err());
+        assert_eq!(format_duration(3 * 86400 + 4 * 3600 + 59), "3d 4h");
+    }
+
+    // Build a .tar.gz holding `entries`, naming them without validation so
+    // unsafe paths can be written too
+    fn make_archive(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            File::create(path).unwrap(),
+            flate2::Compression::fast(),
+        ));
+        for (name, data) in entries {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_extract_binary() {
+        let dir = std::env::temp_dir().join(format!("thumed_extract_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("helm.tar.gz");
+        let helm = dir.join("helm");
+
+        make_archive(
+            &archive,
+            &[("linux-amd64/README.md", b"readme"), ("./linux-amd64/helm", b"binary")],
+        );
+        extract_binary(&archive, "linux-amd64/helm", &helm).unwrap();
+        assert_eq!(fs::read(&helm).unwrap(), b"binary");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&helm).unwrap().permissions().mode() & 0o111, 0o111);
+        }
+        assert!(extract_binary(&archive, "linux-amd64/kubectl", &dir.join("kubectl")).is_err());
+
+        make_archive(&archive, &[("../evil", b"x"), ("linux-amd64/helm", b"binary")]);
+        assert!(extract_binary(&archive, "linux-amd64/helm", &dir.join("helm2")).is_err());
+        assert!(!dir.join("helm2").exists());
+        assert!(!std::env::temp_dir().join("evil").exists());
+
+        // Windows releases are zip archives
+        let zip_archive = dir.join("helm.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&zip_archive).unwrap());
+        writer
+            .start_file("windows-amd64/helm.exe", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        io::Write::write_all(&mut writer, b"binary").unwrap();
+        writer.finish().unwrap();
+        let helm_exe = dir.join("helm.exe");
+        extract_binary(&zip_archive, "windows-amd64/helm.exe", &helm_exe).unwrap();
+        assert_eq!(fs::read(&helm_exe).unwrap(), b"binary");
+        assert!(extract_binary(&zip_archive, "linux-amd64/helm", &dir.join("helm3")).is_err());
+        fs::remove_file(&zip_archive).unwrap();
+        fs::remove_file(&helm_exe).unwrap();
+
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }